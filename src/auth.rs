@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::units::grant::Grant;
+
+type HmacSha256 = Hmac<Sha256>;
+
+//shortest HMAC secret accepted, in bytes, anything shorter is too easy to guess tokens for
+pub const MIN_HMAC_SECRET_LEN: usize = 32;
+
+//Principal, who a token belongs to
+//grant narrows what the client's path allows, None leaves the path's grant as it is
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    pub user_id: String,
+    #[serde(default)]
+    pub grant: Option<Grant>,
+}
+
+//Principal functions
+impl Principal {
+    /// Returns what the principal may touch on a connection whose path allows path_grant
+    pub fn effective_grant(&self, path_grant: &Grant) -> Grant {
+        match &self.grant {
+            Some(grant) => path_grant.intersect(grant),
+            None => path_grant.clone(),
+        }
+    }
+}
+
+//signed part of an HMAC token, exp is a unix time in seconds
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+struct TokenClaims {
+    user_id: String,
+    exp: u64,
+    #[serde(default)]
+    grant: Option<Grant>,
+}
+
+//Authenticator, checks bearer tokens
+//tokens: fixed tokens from the local key store, hmac_secret: key for signed tokens,
+//which look like hex(claims JSON).hex(HMAC-SHA256 of the first part)
+//with neither set every client is let in without a principal
+#[derive(Debug, Default)]
+pub struct Authenticator {
+    tokens: HashMap<String, Principal>,
+    hmac_secret: Option<Vec<u8>>,
+}
+
+//Authenticator functions
+impl Authenticator {
+    //constructor
+    pub fn new(tokens: HashMap<String, Principal>, hmac_secret: Option<Vec<u8>>) -> Authenticator {
+        Authenticator { tokens, hmac_secret }
+    }
+
+    /// Reads a key store file, a JSON object of token to Principal
+    pub fn load_tokens(path: &str) -> Result<HashMap<String, Principal>, String> {
+        let json = std::fs::read_to_string(path).map_err(|err| format!("can't read {}: {}", path, err))?;
+
+        serde_json::from_str(&json).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Returns whether clients have to present a token
+    pub fn enabled(&self) -> bool {
+        !self.tokens.is_empty() || self.hmac_secret.is_some()
+    }
+
+    /// Returns who is making a request from its Authorization header value or ?token=,
+    /// None when authentication is off, an error when it's on and there's no good token
+    pub fn caller(&self, authorization: Option<&str>, params: &[(String, String)]) -> Result<Option<Principal>, String> {
+        if !self.enabled() {
+            return Ok(None);
+        }
+
+        let token = bearer_token(authorization, params).ok_or("missing token")?;
+
+        self.authenticate(&token).map(Some)
+    }
+
+    /// Returns the principal a token belongs to, key store tokens first, then signed ones
+    pub fn authenticate(&self, token: &str) -> Result<Principal, String> {
+        if let Some(principal) = self.tokens.get(token) {
+            return Ok(principal.clone());
+        }
+
+        let secret = match &self.hmac_secret {
+            Some(secret) => secret,
+            None => return Err("unknown token".to_owned()),
+        };
+
+        let (claims_hex, signature_hex) = token.split_once('.').ok_or("unknown token")?;
+        let signature = hex::decode(signature_hex).map_err(|_| "malformed token")?;
+
+        let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+        mac.update(claims_hex.as_bytes());
+        mac.verify_slice(&signature).map_err(|_| "bad token signature")?;
+
+        let claims_json = hex::decode(claims_hex).map_err(|_| "malformed token")?;
+        let claims: TokenClaims = serde_json::from_slice(&claims_json).map_err(|_| "malformed token")?;
+
+        if claims.exp <= now() {
+            return Err("token expired".to_owned());
+        }
+
+        Ok(Principal { user_id: claims.user_id, grant: claims.grant })
+    }
+
+    /// Makes a signed token for user_id that expires at exp, in unix seconds
+    pub fn sign(&self, user_id: &str, exp: u64, grant: Option<Grant>) -> Result<String, String> {
+        let secret = self.hmac_secret.as_ref().ok_or("no hmac secret configured")?;
+        let claims = TokenClaims { user_id: user_id.to_owned(), exp, grant };
+        let claims_hex = hex::encode(serde_json::to_string(&claims).unwrap());
+
+        let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+        mac.update(claims_hex.as_bytes());
+
+        Ok(format!("{}.{}", claims_hex, hex::encode(mac.finalize().into_bytes())))
+    }
+}
+
+/// Pulls the bearer token out of an Authorization header value or the query's token parameter
+pub fn bearer_token(authorization: Option<&str>, params: &[(String, String)]) -> Option<String> {
+    if let Some(token) = authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+        return Some(token.trim().to_owned());
+    }
+
+    params.iter().find(|(name, _)| name == "token").map(|(_, value)| value.clone())
+}
+
+/// Checks a configured HMAC secret is long enough to sign tokens with
+pub fn parse_hmac_secret(secret: &str) -> Result<String, String> {
+    if secret.len() < MIN_HMAC_SECRET_LEN {
+        return Err(format!("has to be at least {} bytes", MIN_HMAC_SECRET_LEN));
+    }
+
+    Ok(secret.to_owned())
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authenticator(secret: &str) -> Authenticator {
+        Authenticator::new(HashMap::new(), Some(secret.as_bytes().to_vec()))
+    }
+
+    fn secret() -> String {
+        "k".repeat(MIN_HMAC_SECRET_LEN)
+    }
+
+    #[test]
+    fn signed_token_round_trips() {
+        let auth = authenticator(&secret());
+        let grant = Grant::new(&["/stories"], &["Stories"]);
+        let token = auth.sign("alice", now() + 60, Some(grant.clone())).unwrap();
+
+        let principal = auth.authenticate(&token).unwrap();
+        assert_eq!(principal, Principal { user_id: "alice".to_owned(), grant: Some(grant) });
+    }
+
+    #[test]
+    fn expired_token_is_refused() {
+        let auth = authenticator(&secret());
+        let token = auth.sign("alice", now() - 1, None).unwrap();
+
+        assert_eq!(auth.authenticate(&token), Err("token expired".to_owned()));
+    }
+
+    #[test]
+    fn tampered_token_is_refused() {
+        let auth = authenticator(&secret());
+        let token = auth.sign("alice", now() + 60, None).unwrap();
+        let (claims_hex, signature_hex) = token.split_once('.').unwrap();
+
+        //a flipped bit in the signature
+        let mut signature = hex::decode(signature_hex).unwrap();
+        signature[0] ^= 1;
+        let forged = format!("{}.{}", claims_hex, hex::encode(signature));
+        assert_eq!(auth.authenticate(&forged), Err("bad token signature".to_owned()));
+
+        //someone else's user_id under the original signature
+        let claims = TokenClaims { user_id: "mallory".to_owned(), exp: now() + 60, grant: None };
+        let forged = format!("{}.{}", hex::encode(serde_json::to_string(&claims).unwrap()), signature_hex);
+        assert_eq!(auth.authenticate(&forged), Err("bad token signature".to_owned()));
+    }
+
+    #[test]
+    fn token_signed_with_another_key_is_refused() {
+        let token = authenticator(&"o".repeat(MIN_HMAC_SECRET_LEN)).sign("alice", now() + 60, None).unwrap();
+
+        assert_eq!(authenticator(&secret()).authenticate(&token), Err("bad token signature".to_owned()));
+    }
+
+    #[test]
+    fn key_store_tokens_are_looked_up() {
+        let principal = Principal { user_id: "alice".to_owned(), grant: None };
+        let tokens = [("t0ken".to_owned(), principal.clone())].iter().cloned().collect();
+        let auth = Authenticator::new(tokens, None);
+
+        assert_eq!(auth.authenticate("t0ken"), Ok(principal));
+        assert_eq!(auth.authenticate("other"), Err("unknown token".to_owned()));
+    }
+
+    #[test]
+    fn caller_needs_a_token_only_with_authentication_on() {
+        assert_eq!(Authenticator::default().caller(None, &[]), Ok(None));
+
+        //these are turned away with a 401
+        let auth = authenticator(&secret());
+        assert_eq!(auth.caller(None, &[]), Err("missing token".to_owned()));
+        assert!(auth.caller(Some("Bearer nonsense"), &[]).is_err());
+
+        let token = auth.sign("alice", now() + 60, None).unwrap();
+        let params = vec![("token".to_owned(), token.clone())];
+        assert_eq!(auth.caller(None, &params).unwrap().unwrap().user_id, "alice");
+        assert_eq!(auth.caller(Some(&format!("Bearer {}", token)), &[]).unwrap().unwrap().user_id, "alice");
+    }
+
+    #[test]
+    fn principal_grant_narrows_the_path_grant() {
+        let path_grant = Grant::new(&["/stories", "/votes"], &["Stories"]);
+        let principal = Principal { user_id: "alice".to_owned(), grant: Some(Grant::new(&["/votes"], &[])) };
+        let effective = principal.effective_grant(&path_grant);
+
+        //these are turned away with a 403
+        assert!(effective.check_read("/stories").is_err());
+        assert!(effective.check_write("Stories").is_err());
+        assert!(effective.check_read("/votes").is_ok());
+
+        let unrestricted = Principal { user_id: "bob".to_owned(), grant: None };
+        assert_eq!(unrestricted.effective_grant(&path_grant), path_grant);
+    }
+
+    #[test]
+    fn short_secrets_are_rejected() {
+        assert!(parse_hmac_secret("").is_err());
+        assert!(parse_hmac_secret(&"k".repeat(MIN_HMAC_SECRET_LEN - 1)).is_err());
+        assert_eq!(parse_hmac_secret(&secret()), Ok(secret()));
+    }
+}
@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+use clap::Parser;
+use clap::builder::RangedU64ValueParser;
+
+use crate::auth::parse_hmac_secret;
+use crate::httpserver::{HttpLimits, DEFAULT_POLL_TIMEOUT};
+use crate::readiness::DEFAULT_GATE_TIMEOUT;
+use crate::subscribers::channel::{QueueConfig, DEFAULT_QUEUE_CAPACITY};
+use crate::subscribers::longpoll::IDLE_TIMEOUT;
+use crate::types::slowconsumerpolicy::SlowConsumerPolicy;
+use crate::viewsandgraphs::writededup::DEFAULT_DEDUP_WINDOW;
+
+//Config, how the server is started, every flag can also be set by the NORIA_ environment variable next to it
+//flags win over the environment, which wins over the defaults
+#[derive(Parser, Debug, Clone)]
+#[command(name = "noria-server", version, about = "Partially stateful dataflow server for websocket clients")]
+pub struct Config {
+    /// Address both listeners bind, 0.0.0.0 to take connections from outside the host
+    #[arg(long, env = "NORIA_BIND_ADDR", default_value = "127.0.0.1")]
+    pub bind_addr: String,
+
+    /// Websocket port
+    #[arg(long, env = "NORIA_WS_PORT", default_value_t = 3012)]
+    pub ws_port: u16,
+
+    /// Plain HTTP port
+    #[arg(long, env = "NORIA_HTTP_PORT", default_value_t = 3013)]
+    pub http_port: u16,
+
+    /// Websocket clients served at once, connections past it are closed straight away
+    #[arg(long, env = "NORIA_MAX_CONNECTIONS", default_value_t = 1024, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_connections: usize,
+
+    /// HTTP requests answered at once, long-polls included, requests past it get a 503
+    #[arg(long, env = "NORIA_MAX_HTTP_REQUESTS", default_value_t = 256, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_http_requests: usize,
+
+    /// Largest websocket message or HTTP body a client may send, in bytes
+    #[arg(long, env = "NORIA_MAX_MESSAGE_SIZE", default_value_t = 64 << 20, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_message_size: usize,
+
+    /// Longest an HTTP long-poll waits for a change, in seconds, up to how long an unpolled subscription lives
+    #[arg(long, env = "NORIA_MAX_POLL_TIMEOUT_SECS", default_value_t = DEFAULT_POLL_TIMEOUT.as_secs(),
+        value_parser = RangedU64ValueParser::<u64>::new().range(1..=IDLE_TIMEOUT.as_secs()))]
+    pub max_poll_timeout_secs: u64,
+
+    /// Tokio worker threads, the number of cores if left out
+    #[arg(long, env = "NORIA_WORKERS", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub workers: Option<usize>,
+
+    /// env_logger filter, a level like info or per module like noria_server=debug
+    #[arg(long, env = "NORIA_LOG_LEVEL", default_value = "warn")]
+    pub log_level: String,
+
+    /// How long retried writes are recognised by their client_id and write_id, in seconds
+    #[arg(long, env = "NORIA_DEDUP_WINDOW_SECS", default_value_t = DEFAULT_DEDUP_WINDOW.as_secs())]
+    pub dedup_window_secs: u64,
+
+    /// Messages each connection's outgoing queue holds
+    #[arg(long, env = "NORIA_QUEUE_CAPACITY", default_value_t = DEFAULT_QUEUE_CAPACITY, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub queue_capacity: usize,
+
+    /// What happens to a client whose queue is full: drop, coalesce or block
+    #[arg(long, env = "NORIA_SLOW_CONSUMER_POLICY", default_value = "coalesce")]
+    pub slow_consumer_policy: SlowConsumerPolicy,
+
+    /// Holds writers back until this many have connected, for latency runs
+    #[arg(long, env = "NORIA_BENCHMARK_PARTICIPANTS")]
+    pub benchmark_participants: Option<usize>,
+
+    /// How long writers wait for the rest of the benchmark participants, in seconds
+    #[arg(long, env = "NORIA_BENCHMARK_TIMEOUT_SECS", default_value_t = DEFAULT_GATE_TIMEOUT.as_secs())]
+    pub benchmark_timeout_secs: u64,
+
+    /// Key store of token to principal, as JSON
+    #[arg(long, env = "NORIA_AUTH_TOKENS_FILE")]
+    pub auth_tokens_file: Option<String>,
+
+    /// Secret signed tokens are checked against, at least 32 bytes
+    #[arg(long, env = "NORIA_AUTH_HMAC_SECRET", hide_env_values = true, value_parser = parse_hmac_secret)]
+    pub auth_hmac_secret: Option<String>,
+
+    /// PEM certificate chain, serves wss:// along with --tls-key-file
+    #[arg(long, env = "NORIA_TLS_CERT_FILE", requires = "tls_key_file")]
+    pub tls_cert_file: Option<String>,
+
+    /// PEM private key for --tls-cert-file
+    #[arg(long, env = "NORIA_TLS_KEY_FILE", requires = "tls_cert_file")]
+    pub tls_key_file: Option<String>,
+}
+
+//Config functions
+impl Config {
+    /// Returns the websocket listener's address
+    pub fn ws_addr(&self) -> String {
+        format!("{}:{}", self.bind_addr, self.ws_port)
+    }
+
+    /// Returns the HTTP listener's address
+    pub fn http_addr(&self) -> String {
+        format!("{}:{}", self.bind_addr, self.http_port)
+    }
+
+    /// Returns the outgoing queue settings for each connection
+    pub fn queue_config(&self) -> QueueConfig {
+        QueueConfig { capacity: self.queue_capacity, policy: self.slow_consumer_policy }
+    }
+
+    /// Returns the bounds on HTTP requests
+    pub fn http_limits(&self) -> HttpLimits {
+        HttpLimits {
+            max_requests: self.max_http_requests,
+            max_body_size: self.max_message_size,
+            max_poll_timeout: Duration::from_secs(self.max_poll_timeout_secs),
+        }
+    }
+
+    /// Returns how long retried writes are deduplicated for
+    pub fn dedup_window(&self) -> Duration {
+        Duration::from_secs(self.dedup_window_secs)
+    }
+
+    /// Returns how long benchmark writers wait at the gate
+    pub fn benchmark_timeout(&self) -> Duration {
+        Duration::from_secs(self.benchmark_timeout_secs)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, clap::Error> {
+        Config::try_parse_from(std::iter::once("noria-server").chain(args.iter().cloned()))
+    }
+
+    #[test]
+    fn counts_of_zero_are_rejected() {
+        for flag in ["--workers", "--max-connections", "--max-http-requests", "--max-message-size", "--queue-capacity"].iter() {
+            assert!(parse(&[flag, "0"]).is_err(), "{} 0 was accepted", flag);
+            assert!(parse(&[flag, "1"]).is_ok(), "{} 1 was rejected", flag);
+        }
+    }
+
+    #[test]
+    fn short_hmac_secrets_are_rejected() {
+        assert!(parse(&["--auth-hmac-secret", ""]).is_err());
+        assert!(parse(&["--auth-hmac-secret", "secret"]).is_err());
+        assert!(parse(&["--auth-hmac-secret", &"s".repeat(32)]).is_ok());
+    }
+
+    #[test]
+    fn poll_timeout_stays_under_the_idle_timeout() {
+        assert!(parse(&["--max-poll-timeout-secs", "0"]).is_err());
+        assert!(parse(&["--max-poll-timeout-secs", &(IDLE_TIMEOUT.as_secs() + 1).to_string()]).is_err());
+        assert_eq!(parse(&["--max-poll-timeout-secs", "5"]).unwrap().http_limits().max_poll_timeout, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn tls_files_go_together() {
+        assert!(parse(&["--tls-cert-file", "cert.pem"]).is_err());
+        assert!(parse(&["--tls-cert-file", "cert.pem", "--tls-key-file", "key.pem"]).is_ok());
+    }
+}
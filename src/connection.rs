@@ -0,0 +1,499 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::auth::Principal;
+use crate::subscribers::next_subscription_id;
+use crate::subscribers::channel::{ChannelSubscriber, QueueConfig};
+use crate::types::datatype::DataType;
+use crate::units::clientmessage::ClientMessage;
+use crate::units::grant::Grant;
+use crate::units::readrequest::ReadResponse;
+use crate::units::servererror::ServerError;
+use crate::units::subscription::{Resume, SubscribeRequest, SubscribeResponse};
+use crate::units::writeack::WriteAck;
+use crate::viewsandgraphs::dfg::DataFlowGraph;
+
+//how often a connection pings its client
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+//how long a client can go without sending anything, pongs included, before it's taken for dead
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(45);
+
+//websocket connections being served right now
+static CONNECTED: AtomicUsize = AtomicUsize::new(0);
+
+//tests that serve websockets take this, so one can count its own clients without the others moving the number
+#[cfg(test)]
+pub(crate) static CONNECTED_TESTS: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Returns the number of websocket clients connected right now
+pub fn connected_clients() -> usize {
+    CONNECTED.load(Ordering::Relaxed)
+}
+
+//Connection, the Leaf subscriptions a client's websocket holds
+//Leafs push changes into queue without waiting on the network, a writer task drains it onto the socket
+//grant: the Leaves and Roots the client may use, checked on every message
+//principal: who the client authenticated as, None when authentication is off
+//heartbeat_interval and heartbeat_timeout: how often the client is pinged and how long it may stay silent
+pub struct Connection {
+    queue: Arc<ChannelSubscriber>,
+    subscriptions: HashMap<u64, usize>,
+    grant: Grant,
+    principal: Option<Principal>,
+    heartbeat_interval: Duration,
+    heartbeat_timeout: Duration,
+}
+
+//Connection functions
+impl Connection {
+    //constructor
+    pub fn new(config: QueueConfig, grant: Grant, principal: Option<Principal>) -> Connection {
+        let queue = ChannelSubscriber::new(config);
+        let subscriptions = HashMap::new();
+
+        Connection { queue, subscriptions, grant, principal, heartbeat_interval: HEARTBEAT_INTERVAL, heartbeat_timeout: HEARTBEAT_TIMEOUT }
+    }
+
+    /// Returns the Connection pinging every interval and dropping a client silent for timeout
+    pub fn with_heartbeat(mut self, interval: Duration, timeout: Duration) -> Connection {
+        self.heartbeat_interval = interval;
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Returns the authenticated user, None if auth is off
+    fn user_id(&self) -> Option<&str> {
+        self.principal.as_ref().map(|principal| principal.user_id.as_str())
+    }
+
+    /// Queues a message for the client behind anything already waiting,
+    /// a full queue is closed instead and the connection ends once the writer has sent what it holds
+    pub fn send(&self, json: String) {
+        self.queue.push_reply(json).unwrap_or(());
+    }
+
+    /// Subscribes to the Leaf at leaf_path, returns the new subscription id
+    /// since resumes from where the client left off, see Leaf::subscribe
+    pub fn subscribe(&mut self, graph: &DataFlowGraph, leaf_path: &str, 
+        keys: Option<HashSet<DataType>>, since: Option<Resume>) -> Result<u64, String> {
+        self.grant.check_read(leaf_path)?;
+
+        let leaf_index = graph.leaf_index(leaf_path).ok_or(format!("no leaf at {}", leaf_path))?;
+        let id = next_subscription_id();
+
+        graph.subscribe(leaf_index, id, self.queue.clone(), keys, since, self.user_id())?;
+        self.subscriptions.insert(id, leaf_index);
+
+        Ok(id)
+    }
+
+    pub fn unsubscribe(&mut self, graph: &DataFlowGraph, id: u64) -> Result<u64, String> {
+        match self.subscriptions.remove(&id) {
+            Some(leaf_index) => {
+                graph.unsubscribe(leaf_index, id);
+                Ok(id)
+            },
+            None => Err(format!("no subscription {} on this connection", id)),
+        }
+    }
+
+    /// Serves the connection until the client leaves or stops answering pings, then drops its subscriptions
+    /// changes from Leafs and replies to the client go out in the order they were queued
+    /// the stream is a plain TCP one or a TLS one wrapping it
+    pub async fn run<S>(mut self, websocket: WebSocketStream<S>, graph: &RwLock<DataFlowGraph>)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut outgoing, mut incoming) = websocket.split();
+        let queue = Arc::clone(&self.queue);
+        let (heartbeat_interval, heartbeat_timeout) = (self.heartbeat_interval, self.heartbeat_timeout);
+
+        CONNECTED.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(principal) = &self.principal {
+            info!("user {} connected", principal.user_id);
+        }
+
+        //sends on their own task, so a client that reads slowly never holds up its own writes
+        let mut writer = tokio::spawn(async move {
+            let mut heartbeat = tokio::time::interval(heartbeat_interval);
+
+            loop {
+                let message = tokio::select! {
+                    json = queue.recv() => match json {
+                        Some(json) => Message::text(json),
+                        None => break,
+                    },
+                    _ = heartbeat.tick() => Message::Ping(Vec::new()),
+                };
+
+                if outgoing.send(message).await.is_err() {
+                    break;
+                }
+            }
+
+            queue.close();
+            outgoing.close().await.unwrap_or(());
+        });
+
+        let mut writer_done = false;
+        let mut evicted = false;
+
+        loop {
+            let message = tokio::select! {
+                message = tokio::time::timeout(heartbeat_timeout, incoming.next()) => message,
+                //the socket failed or the queue was dropped for falling behind
+                _ = &mut writer => {
+                    writer_done = true;
+                    break;
+                },
+            };
+
+            //nothing at all, not even a pong, since the last few pings
+            let message = match message {
+                Ok(message) => message,
+                Err(_) => {
+                    evicted = true;
+                    break;
+                },
+            };
+
+            match message {
+                Some(Ok(Message::Text(json))) => {
+                    //a write, or the reply to anything, can wait on a full queue under SlowConsumerPolicy::Block,
+                    //so off the runtime
+                    tokio::task::block_in_place(|| {
+                        let reply = {
+                            let g = graph.read().unwrap();
+                            self.handle_message(&g, &json)
+                        };
+
+                        if let Some(reply_json) = reply {
+                            self.send(reply_json);
+                        }
+                    });
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => (),
+            }
+        }
+
+        //unsubscribing takes Leaf locks a write blocked on some other full queue can be holding
+        tokio::task::block_in_place(|| self.close(graph));
+
+        //a dead peer can leave the writer stuck on a send that never finishes
+        if evicted {
+            writer.abort();
+        } else if !writer_done {
+            writer.await.unwrap_or(());
+        }
+
+        CONNECTED.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    //answers a single text message from the client, returns the reply if there is one
+    //bad writes are reported back instead of taking down the thread
+    fn handle_message(&mut self, graph: &DataFlowGraph, json: &str) -> Option<String> {
+        match ClientMessage::parse(json) {
+            Ok(ClientMessage::Write(sc)) => {
+                let root_id = sc.root_id.clone();
+                let request_id = sc.request_id;
+                let result = self.grant.check_write(&root_id).and_then(|()| graph.write(sc, self.user_id()));
+
+                //writers that sent a request_id hear back either way, older ones only on errors
+                match (request_id, result) {
+                    (Some(request_id), result) => Some(serde_json::to_string(&WriteAck::new(request_id, root_id, result)).unwrap()),
+                    (None, Ok(())) => None,
+                    (None, Err(err)) => Some(serde_json::to_string(&ServerError::new(root_id, err)).unwrap()),
+                }
+            },
+            Ok(ClientMessage::Read(request)) => {
+                let response = match self.grant.check_read(&request.leaf) {
+                    Ok(()) => graph.read_request(&request, self.user_id()),
+                    Err(err) => ReadResponse::new(request.request_id, Err(err)),
+                };
+
+                Some(serde_json::to_string(&response).unwrap())
+            },
+            Ok(ClientMessage::Subscribe(request)) => {
+                let SubscribeRequest { request_id, leaf, keys, since, epoch } = request;
+                let keys = keys.map(|keys| keys.into_iter().collect());
+                let result = Resume::from_parts(since, epoch).and_then(|since| self.subscribe(graph, &leaf, keys, since));
+
+                Some(serde_json::to_string(&SubscribeResponse::new(request_id, result)).unwrap())
+            },
+            Ok(ClientMessage::Unsubscribe(request)) => {
+                let result = self.unsubscribe(graph, request.subscription_id);
+
+                Some(serde_json::to_string(&SubscribeResponse::new(request.request_id, result)).unwrap())
+            },
+            Err(err) => Some(serde_json::to_string(&ServerError::new("".to_owned(), err.to_string())).unwrap()),
+        }
+    }
+
+    fn close(&mut self, graph: &RwLock<DataFlowGraph>) {
+        self.queue.close();
+
+        let g = graph.read().unwrap();
+
+        for (id, leaf_index) in self.subscriptions.drain() {
+            g.unsubscribe(leaf_index, id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::leaf::PRIMARY_INDEX;
+    use crate::types::changetype::ChangeType;
+    use crate::units::change::Change;
+    use crate::units::readrequest::ReadRequest;
+    use crate::units::row::Row;
+    use crate::units::serverchange::ServerChange;
+    use tokio_tungstenite::tungstenite::protocol::{Role, WebSocketConfig};
+
+    //the Stories root is node 0, its Leaf at /latencytestleft node 3
+    const LEFT_LEAF: usize = 3;
+
+    fn connection(path: &str) -> Connection {
+        Connection::new(QueueConfig::default(), crate::build_server_info().grant(path), None)
+    }
+
+    fn story(author: i32, id: i32) -> Row {
+        Row::new(vec![DataType::Int(author), DataType::Int(id)])
+    }
+
+    fn write(request_id: u64, root_id: &str, row: Row) -> String {
+        let mut sc = ServerChange::new(root_id.to_owned(), vec![Change::new(ChangeType::Insertion, vec![row])]);
+        sc.request_id = Some(request_id);
+
+        serde_json::to_string(&ClientMessage::Write(sc)).unwrap()
+    }
+
+    fn read(request_id: u64, leaf: &str) -> String {
+        let request = ReadRequest { request_id, leaf: leaf.to_owned(), index: PRIMARY_INDEX.to_owned(), keys: vec![DataType::Int(1)], range: None };
+
+        serde_json::to_string(&ClientMessage::Read(Box::new(request))).unwrap()
+    }
+
+    fn subscribe(request_id: u64, leaf: &str) -> String {
+        let request = SubscribeRequest { request_id, leaf: leaf.to_owned(), keys: None, since: None, epoch: None };
+
+        serde_json::to_string(&ClientMessage::Subscribe(request)).unwrap()
+    }
+
+    fn reply<T: serde::de::DeserializeOwned>(connection: &mut Connection, graph: &DataFlowGraph, json: &str) -> T {
+        serde_json::from_str(&connection.handle_message(graph, json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn read_path_can_read_and_subscribe_to_its_leaf_only() {
+        let graph = crate::build_server_graph();
+        let mut connection = connection("/latencytestleft");
+
+        let response: ReadResponse = reply(&mut connection, &graph, &read(1, "/latencytestleft"));
+        assert_eq!(response.error, None);
+        let response: ReadResponse = reply(&mut connection, &graph, &read(2, "/latencytestright"));
+        assert_eq!(response.error, Some("not allowed to read /latencytestright".to_owned()));
+
+        let response: SubscribeResponse = reply(&mut connection, &graph, &subscribe(3, "/latencytestleft"));
+        assert!(response.subscription_id.is_some());
+        let response: SubscribeResponse = reply(&mut connection, &graph, &subscribe(4, "/latencytestright"));
+        assert_eq!(response.error, Some("not allowed to read /latencytestright".to_owned()));
+        assert_eq!(connection.subscriptions.len(), 1);
+
+        //and can't write even to the root behind its leaf
+        let ack: WriteAck = reply(&mut connection, &graph, &write(5, "Stories", story(1, 1)));
+        assert_eq!(ack.error, Some("not allowed to write Stories".to_owned()));
+        assert!(graph.lookup(LEFT_LEAF, PRIMARY_INDEX, &DataType::Int(1), None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn write_path_can_write_its_roots_only() {
+        let graph = crate::build_server_graph();
+        let mut connection = connection("/dummytestread");
+
+        let ack: WriteAck = reply(&mut connection, &graph, &write(1, "Stories", story(1, 1)));
+        assert_eq!(ack.error, None);
+        assert_eq!(graph.lookup(LEFT_LEAF, PRIMARY_INDEX, &DataType::Int(1), None).unwrap(), vec![story(1, 1)]);
+
+        let ack: WriteAck = reply(&mut connection, &graph, &write(2, "Users", story(1, 2)));
+        assert_eq!(ack.error, Some("not allowed to write Users".to_owned()));
+
+        let response: ReadResponse = reply(&mut connection, &graph, &read(3, "/latencytestleft"));
+        assert_eq!(response.error, Some("not allowed to read /latencytestleft".to_owned()));
+        let response: SubscribeResponse = reply(&mut connection, &graph, &subscribe(4, "/latencytestleft"));
+        assert_eq!(response.error, Some("not allowed to read /latencytestleft".to_owned()));
+    }
+
+    #[test]
+    fn unknown_path_gets_no_grant() {
+        let graph = crate::build_server_graph();
+        let mut connection = connection("/nothing");
+
+        let ack: WriteAck = reply(&mut connection, &graph, &write(1, "Stories", story(1, 1)));
+        assert!(ack.error.is_some());
+        let response: ReadResponse = reply(&mut connection, &graph, &read(2, "/latencytestleft"));
+        assert!(response.error.is_some());
+    }
+
+    //serves one websocket client on the test graph, subscribed to the Stories Leaf and allowed to write Stories,
+    //returns the client, the graph and the task running the connection
+    async fn serve_one(timeout: Duration, config: Option<WebSocketConfig>) -> (WebSocketStream<tokio::io::DuplexStream>, Arc<RwLock<DataFlowGraph>>, tokio::task::JoinHandle<()>) {
+        let graph = Arc::new(RwLock::new(crate::build_server_graph()));
+        let (client, server) = tokio::io::duplex(1024);
+
+        let mut connection = connection("/latencytestread").with_heartbeat(timeout / 4, timeout);
+        connection.subscribe(&graph.read().unwrap(), "/latencytestleft", None, None).unwrap();
+
+        let served = Arc::clone(&graph);
+        let task = tokio::spawn(async move {
+            let websocket = WebSocketStream::from_raw_socket(server, Role::Server, config).await;
+            connection.run(websocket, &served).await;
+        });
+
+        (WebSocketStream::from_raw_socket(client, Role::Client, None).await, graph, task)
+    }
+
+    fn subscribers(graph: &RwLock<DataFlowGraph>) -> usize {
+        match &*graph.read().unwrap().data.node_weight(petgraph::graph::NodeIndex::new(LEFT_LEAF)).unwrap().read().unwrap() {
+            crate::operators::operation::Operation::Leafor(leaf) => leaf.subscriptions.len(),
+            _ => panic!("node {} is not a leaf", LEFT_LEAF),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn silent_peer_is_evicted() {
+        let _counting = CONNECTED_TESTS.lock().await;
+        let before = connected_clients();
+
+        //the client never reads, so it never answers a ping
+        let (_client, graph, task) = serve_one(Duration::from_millis(200), None).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(connected_clients(), before + 1);
+
+        tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+        assert_eq!(connected_clients(), before);
+        assert_eq!(subscribers(&graph), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn peer_answering_pings_stays_connected() {
+        let _counting = CONNECTED_TESTS.lock().await;
+        let before = connected_clients();
+
+        //reading answers the pings
+        let (mut client, graph, task) = serve_one(Duration::from_millis(200), None).await;
+        let reading = tokio::spawn(async move { while let Some(Ok(_)) = client.next().await {} });
+        tokio::time::sleep(Duration::from_millis(600)).await;
+
+        assert!(!task.is_finished());
+        assert_eq!(connected_clients(), before + 1);
+        assert_eq!(subscribers(&graph), 1);
+
+        reading.abort();
+        tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+        assert_eq!(connected_clients(), before);
+    }
+
+    async fn next_text(client: &mut WebSocketStream<tokio::io::DuplexStream>) -> String {
+        loop {
+            match tokio::time::timeout(Duration::from_secs(5), client.next()).await.unwrap().unwrap().unwrap() {
+                Message::Text(json) => return json,
+                _ => continue,
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn write_is_acked_after_it_is_applied() {
+        let _counting = CONNECTED_TESTS.lock().await;
+        let (mut client, graph, _task) = serve_one(HEARTBEAT_TIMEOUT, None).await;
+        let snapshot: ServerChange = serde_json::from_str(&next_text(&mut client).await).unwrap();
+        assert!(snapshot.snapshot);
+
+        client.send(Message::text(write(41, "Stories", story(1, 1)))).await.unwrap();
+
+        //the Leaf queued the change while applying the write, so the client hears it before the ack
+        let change: ServerChange = serde_json::from_str(&next_text(&mut client).await).unwrap();
+        assert_eq!(change.changes[0].batch, vec![story(1, 1)]);
+
+        let ack: WriteAck = serde_json::from_str(&next_text(&mut client).await).unwrap();
+        assert_eq!(ack, WriteAck::new(41, "Stories".to_owned(), Ok(())));
+        assert_eq!(graph.read().unwrap().lookup(LEFT_LEAF, PRIMARY_INDEX, &DataType::Int(1), None).unwrap(), vec![story(1, 1)]);
+    }
+
+    #[test]
+    fn rejected_write_is_acked_with_its_error() {
+        let graph = crate::build_server_graph();
+        let mut connection = connection("/latencytestread");
+        let bad = Row::new(vec![DataType::Int(1), DataType::Text("one".to_owned())]);
+
+        let ack: WriteAck = reply(&mut connection, &graph, &write(42, "Stories", bad));
+        assert_eq!(ack.request_id, 42);
+        assert!(ack.error.is_some());
+        assert!(graph.lookup(LEFT_LEAF, PRIMARY_INDEX, &DataType::Int(1), None).unwrap().is_empty());
+
+        //without a request_id a write that went through gets no reply at all
+        let sc = ServerChange::new("Stories".to_owned(), vec![Change::new(ChangeType::Insertion, vec![story(1, 1)])]);
+        assert_eq!(connection.handle_message(&graph, &serde_json::to_string(&sc).unwrap()), None);
+    }
+
+    #[test]
+    fn reads_answer_with_rows_or_an_error() {
+        let graph = crate::build_server_graph();
+        graph.change_to_root("Stories".to_owned(), vec![Change::new(ChangeType::Insertion, vec![story(1, 1), story(1, 2)])]).unwrap();
+        let mut connection = connection("/latencytestleft");
+
+        let response: ReadResponse = reply(&mut connection, &graph, &read(1, "/latencytestleft"));
+        assert_eq!(response, ReadResponse::new(1, Ok(vec![story(1, 1)])));
+
+        let mut request = ReadRequest { request_id: 2, leaf: "/latencytestleft".to_owned(), index: "AuthorUserID".to_owned(), keys: vec![DataType::Int(1)], range: None };
+        let response: ReadResponse = reply(&mut connection, &graph, &serde_json::to_string(&ClientMessage::Read(Box::new(request.clone()))).unwrap());
+        assert_eq!(response.rows.len(), 2);
+
+        request.index = "Title".to_owned();
+        let response: ReadResponse = reply(&mut connection, &graph, &serde_json::to_string(&ClientMessage::Read(Box::new(request))).unwrap());
+        assert_eq!(response.request_id, 2);
+        assert!(response.error.unwrap().contains("no index Title"));
+    }
+
+    #[test]
+    fn malformed_messages_get_an_error_back() {
+        let graph = crate::build_server_graph();
+        let mut connection = connection("/latencytestleft");
+
+        let error: ServerError = reply(&mut connection, &graph, r#"{"t":"Read","c":{"request_id":1}}"#);
+        assert!(error.error.contains("leaf"), "{}", error.error);
+        let error: ServerError = reply(&mut connection, &graph, "not json");
+        assert!(!error.error.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn oversized_message_ends_the_connection() {
+        let _counting = CONNECTED_TESTS.lock().await;
+        let config = WebSocketConfig { max_message_size: Some(256), ..WebSocketConfig::default() };
+        let (mut client, _graph, task) = serve_one(HEARTBEAT_TIMEOUT, Some(config)).await;
+
+        let mut request = ReadRequest { request_id: 1, leaf: "/latencytestleft".to_owned(), index: PRIMARY_INDEX.to_owned(), keys: Vec::new(), range: None };
+        request.keys = (0..100).map(DataType::Int).collect();
+        client.send(Message::text(serde_json::to_string(&ClientMessage::Read(Box::new(request))).unwrap())).await.unwrap();
+
+        //the read is never answered, the connection is dropped instead
+        tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+        while let Some(Ok(message)) = client.next().await {
+            if let Message::Text(json) = message {
+                assert!(serde_json::from_str::<ReadResponse>(&json).is_err(), "answered {}", json);
+            }
+        }
+    }
+}
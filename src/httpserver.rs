@@ -0,0 +1,583 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::spawn;
+use std::time::Duration;
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::auth::{Authenticator, Principal};
+use crate::connection::connected_clients;
+use crate::operators::leaf::PRIMARY_INDEX;
+use crate::subscribers::next_subscription_id;
+use crate::subscribers::channel::{queue_metrics, QueueConfig};
+use crate::subscribers::longpoll::LongPollSubscriber;
+use crate::types::datatype::DataType;
+use crate::units::grant::Grant;
+use crate::units::rangequery::RangeQuery;
+use crate::units::serverchange::ServerChange;
+use crate::units::servererror::ServerError;
+use crate::units::writeack::WriteAck;
+use crate::units::subscription::Resume;
+use crate::viewsandgraphs::dfg::DataFlowGraph;
+
+//HTTP/JSON access to the graph for clients that don't speak websockets
+//GET /views/{leaf}?key=..: rows of the Leaf served at /{leaf}, key may repeat, index picks the index
+//  start, end, after, limit and reverse make it a RangeQuery on an ordered index instead
+//POST /roots/{root_id}: ServerChange body, same as a websocket Write, a request_id gets a WriteAck body
+//POST /subscriptions/{leaf}?key=..&since=..&epoch=..: starts a long-poll subscription, replies with its id
+//GET /subscriptions/{id}?timeout=..: ServerChanges since the last poll, waits up to timeout ms for one,
+//  never longer than max_poll_timeout
+//DELETE /subscriptions/{id}: ends a long-poll subscription
+//GET /metrics: connected websocket clients, outgoing queue depths and slow consumer counts
+//each request gets a thread until it's answered, past max_requests at once new ones get a 503,
+//a POST body past max_body_size gets a 413
+//with authentication on every request needs a bearer token, in an Authorization header or ?token=,
+//and only gets at the Leaves and Roots its principal's grant names, without it writes are refused
+
+//how long a poll waits for a change when it doesn't say
+pub const DEFAULT_POLL_TIMEOUT: Duration = Duration::from_secs(30);
+
+//HttpLimits, bounds on what HTTP clients can ask of the server
+//max_requests: answered at once, max_body_size: bytes in a POST body, max_poll_timeout: longest a poll waits
+#[derive(Debug, Clone, Copy)]
+pub struct HttpLimits {
+    pub max_requests: usize,
+    pub max_body_size: usize,
+    pub max_poll_timeout: Duration,
+}
+
+//long-poll subscriptions by id, with the Leaf each one is on and the user_id that made it
+type LongPolls = Mutex<HashMap<u64, (usize, Arc<LongPollSubscriber>, Option<String>)>>;
+
+//a request being answered, counted against max_requests until it's dropped
+struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+//an HTTP reply, status and JSON body
+struct Reply {
+    status: u16,
+    body: String,
+}
+
+impl Reply {
+    fn json(status: u16, body: String) -> Reply {
+        Reply { status, body }
+    }
+
+    fn error(status: u16, error: String) -> Reply {
+        Reply { status, body: serde_json::json!({ "error": error }).to_string() }
+    }
+}
+
+/// Binds the HTTP listener, done before serving so a taken port stops startup
+pub fn bind(addr: &str) -> Result<Server, String> {
+    Server::http(addr).map_err(|err| format!("can't bind HTTP on {}: {}", addr, err))
+}
+
+/// Serves HTTP requests until the process exits, a thread per request and at most limits.max_requests at once
+/// queue_config bounds what each long-poll subscription holds between polls
+pub fn serve(server: Server, graph: Arc<RwLock<DataFlowGraph>>, auth: Arc<Authenticator>, queue_config: QueueConfig, limits: HttpLimits) {
+    let polls: Arc<LongPolls> = Arc::new(Mutex::new(HashMap::new()));
+    let in_flight = Arc::new(AtomicUsize::new(0));
+
+    for request in server.incoming_requests() {
+        //long-polls hold their thread for up to their timeout, so this bounds them too
+        let busy = in_flight.fetch_add(1, Ordering::Relaxed) >= limits.max_requests;
+        let slot = InFlight(Arc::clone(&in_flight));
+
+        if busy {
+            drop(slot);
+            send_reply(request, Reply::error(503, format!("at {} requests, try again later", limits.max_requests)));
+            continue;
+        }
+
+        let graph_ref = Arc::clone(&graph);
+        let polls_ref = Arc::clone(&polls);
+        let auth_ref = Arc::clone(&auth);
+
+        spawn(move || {
+            respond(request, &graph_ref, &polls_ref, &auth_ref, queue_config, limits);
+            drop(slot);
+        });
+    }
+}
+
+//the graph is only read locked while a route uses it, a poll waiting for changes holds no lock
+fn respond(mut request: Request, graph: &RwLock<DataFlowGraph>, polls: &LongPolls, auth: &Authenticator, queue_config: QueueConfig,
+    limits: HttpLimits) {
+    let url = request.url().to_owned();
+    let (path, query) = match url.find('?') {
+        Some(pos) => (&url[..pos], &url[pos + 1..]),
+        None => (&url[..], ""),
+    };
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    let authorization = request.headers().iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .map(|header| header.value.as_str());
+
+    let caller = match auth.caller(authorization, &query_params(query)) {
+        Ok(caller) => caller,
+        Err(err) => return send_reply(request, Reply::error(401, err)),
+    };
+
+    let reply = match (request.method(), segments.as_slice()) {
+        (Method::Get, ["metrics"]) => metrics(),
+        (Method::Get, ["views", leaf]) => {
+            let leaf_path = format!("/{}", decode(leaf));
+
+            match check(&caller, |grant| grant.check_read(&leaf_path)) {
+                Ok(()) => read_view(&graph.read().unwrap(), &leaf_path, query, &caller),
+                Err(reply) => reply,
+            }
+        },
+        (Method::Post, ["roots", root_id]) => {
+            let root_id = decode(root_id);
+
+            match check_write(&caller, &root_id).and_then(|()| read_body(&mut request, limits.max_body_size)) {
+                Ok(body) => write_root(&graph.read().unwrap(), &root_id, &body, &caller),
+                Err(reply) => reply,
+            }
+        },
+        (Method::Post, ["subscriptions", leaf]) => {
+            let leaf_path = format!("/{}", decode(leaf));
+
+            match check(&caller, |grant| grant.check_read(&leaf_path)) {
+                Ok(()) => subscribe(&graph.read().unwrap(), polls, &leaf_path, query, &caller, queue_config),
+                Err(reply) => reply,
+            }
+        },
+        (Method::Get, ["subscriptions", id]) => poll(polls, id, query, &caller, limits.max_poll_timeout),
+        (Method::Delete, ["subscriptions", id]) => unsubscribe(&graph.read().unwrap(), polls, id, &caller),
+        (_, ["views", _]) | (_, ["roots", _]) | (_, ["subscriptions", _]) => Reply::error(405, "method not allowed".to_owned()),
+        _ => Reply::error(404, format!("no route for {}", path)),
+    };
+
+    send_reply(request, reply);
+}
+
+//reads a request body of at most max_size bytes, a longer one isn't read past the limit
+fn read_body(request: &mut Request, max_size: usize) -> Result<String, Reply> {
+    let too_large = || Reply::error(413, format!("body is over {} bytes", max_size));
+
+    if request.body_length().is_some_and(|length| length > max_size) {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    request.as_reader().take(max_size as u64 + 1).read_to_end(&mut body).map_err(|err| Reply::error(400, err.to_string()))?;
+
+    if body.len() > max_size {
+        return Err(too_large());
+    }
+
+    String::from_utf8(body).map_err(|err| Reply::error(400, err.to_string()))
+}
+
+fn send_reply(request: Request, reply: Reply) {
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = Response::from_string(reply.body).with_status_code(reply.status).with_header(header);
+
+    request.respond(response).unwrap_or(());
+}
+
+//runs a grant check for the caller, HTTP has no path so a principal without a grant gets nothing
+//and without authentication everything is allowed
+fn check<F>(caller: &Option<Principal>, check: F) -> Result<(), Reply>
+where
+    F: FnOnce(&Grant) -> Result<(), String>,
+{
+    match caller {
+        Some(principal) => check(principal.grant.as_ref().unwrap_or(&Grant::default())).map_err(|err| Reply::error(403, err)),
+        None => Ok(()),
+    }
+}
+
+//writes are always checked against a grant, without authentication there is none so they are refused
+fn check_write(caller: &Option<Principal>, root_id: &str) -> Result<(), Reply> {
+    match caller {
+        Some(_) => check(caller, |grant| grant.check_write(root_id)),
+        None => Err(Reply::error(403, "writes over HTTP need authentication".to_owned())),
+    }
+}
+
+fn metrics() -> Reply {
+    let mut metrics = serde_json::to_value(queue_metrics()).unwrap();
+    metrics["connected_clients"] = connected_clients().into();
+
+    Reply::json(200, metrics.to_string())
+}
+
+fn read_view(graph: &DataFlowGraph, leaf_path: &str, query: &str, caller: &Option<Principal>) -> Reply {
+    let leaf_index = match graph.leaf_index(leaf_path) {
+        Some(index) => index,
+        None => return Reply::error(404, format!("no leaf at {}", leaf_path)),
+    };
+
+    let params = query_params(query);
+    let param = |name: &str| params.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+
+    let user_id = owner(caller);
+    let index_name = param("index").unwrap_or(PRIMARY_INDEX);
+    let parse_key = |text: &str| graph.parse_key(leaf_index, index_name, text);
+
+    let ranged = ["start", "end", "after", "limit", "reverse"].iter().any(|name| param(name).is_some());
+
+    let result = if ranged {
+        range_query(&param, &parse_key).and_then(|query| graph.range(leaf_index, index_name, &query, user_id.as_deref()))
+    } else {
+        let mut rows = Vec::new();
+
+        if !params.iter().any(|(key, _)| key == "key") {
+            return Reply::error(400, "missing key, or start, end, after, limit or reverse for a range".to_owned());
+        }
+
+        for (_, text) in params.iter().filter(|(key, _)| key == "key") {
+            let found = parse_key(text).and_then(|key| graph.lookup(leaf_index, index_name, &key, user_id.as_deref()));
+
+            match found {
+                Ok(found) => rows.extend(found),
+                Err(err) => return Reply::error(400, err),
+            }
+        }
+
+        Ok(rows)
+    };
+
+    match result {
+        Ok(rows) => Reply::json(200, serde_json::to_string(&rows).unwrap()),
+        Err(err) => Reply::error(400, err),
+    }
+}
+
+//builds a RangeQuery out of query parameters, keys typed by the index's column
+fn range_query<'a, P, K>(param: &P, parse_key: &K) -> Result<RangeQuery, String>
+where
+    P: Fn(&str) -> Option<&'a str>,
+    K: Fn(&str) -> Result<DataType, String>,
+{
+    let key_param = |name: &str| param(name).map(parse_key).transpose();
+
+    let limit = match param("limit") {
+        Some(text) => Some(text.parse::<usize>().map_err(|_| format!("bad limit {:?}", text))?),
+        None => None,
+    };
+    let reverse = match param("reverse") {
+        Some(text) => text.is_empty() || text.parse::<bool>().map_err(|_| format!("bad reverse {:?}", text))?,
+        None => false,
+    };
+
+    Ok(RangeQuery {
+        start: key_param("start")?,
+        end: key_param("end")?,
+        after: key_param("after")?,
+        limit,
+        reverse,
+    })
+}
+
+fn write_root(graph: &DataFlowGraph, root_id: &str, body: &str, caller: &Option<Principal>) -> Reply {
+    if !graph.has_root(root_id) {
+        return Reply::error(404, format!("no root with id {}", root_id));
+    }
+
+    let sc: ServerChange = match serde_json::from_str(body) {
+        Ok(sc) => sc,
+        Err(err) => return Reply::error(400, err.to_string()),
+    };
+
+    if sc.root_id != root_id {
+        return Reply::error(400, format!("body is for root {}, not {}", sc.root_id, root_id));
+    }
+
+    let request_id = sc.request_id;
+    let result = graph.write(sc, owner(caller).as_deref());
+    let status = if result.is_ok() { 200 } else { 400 };
+
+    match (request_id, result) {
+        (Some(request_id), result) => Reply::json(status, serde_json::to_string(&WriteAck::new(request_id, root_id.to_owned(), result)).unwrap()),
+        (None, Ok(())) => Reply::json(204, String::new()),
+        (None, Err(err)) => Reply::json(400, serde_json::to_string(&ServerError::new(root_id.to_owned(), err)).unwrap()),
+    }
+}
+
+fn subscribe(graph: &DataFlowGraph, polls: &LongPolls, leaf_path: &str, query: &str, caller: &Option<Principal>,
+    queue_config: QueueConfig) -> Reply {
+    let leaf_index = match graph.leaf_index(leaf_path) {
+        Some(index) => index,
+        None => return Reply::error(404, format!("no leaf at {}", leaf_path)),
+    };
+
+    let params = query_params(query);
+    let key_params: Vec<&String> = params.iter().filter(|(name, _)| name == "key").map(|(_, value)| value).collect();
+
+    let keys = if key_params.is_empty() {
+        None
+    } else {
+        match key_params.iter().map(|text| graph.parse_key(leaf_index, PRIMARY_INDEX, text)).collect::<Result<HashSet<DataType>, String>>() {
+            Ok(keys) => Some(keys),
+            Err(err) => return Reply::error(400, err),
+        }
+    };
+    let since = match Resume::from_query(&params) {
+        Ok(since) => since,
+        Err(err) => return Reply::error(400, err),
+    };
+
+    //the snapshot or missed changes are waiting for the first poll
+    let id = next_subscription_id();
+    let subscriber = Arc::new(LongPollSubscriber::new(queue_config));
+
+    match graph.subscribe(leaf_index, id, subscriber.clone(), keys, since, owner(caller).as_deref()) {
+        Ok(()) => {
+            let mut polls = polls.lock().unwrap();
+
+            //Leafs have already dropped abandoned subscriptions, or will on their next change
+            polls.retain(|_, (_, subscriber, _)| !subscriber.abandoned());
+            polls.insert(id, (leaf_index, subscriber, owner(caller)));
+            Reply::json(200, serde_json::json!({ "subscription_id": id }).to_string())
+        },
+        Err(err) => Reply::error(400, err),
+    }
+}
+
+fn poll(polls: &LongPolls, id: &str, query: &str, caller: &Option<Principal>, max_timeout: Duration) -> Reply {
+    //someone else's subscription looks the same as one that doesn't exist
+    let subscriber = match id.parse::<u64>().ok().and_then(|id| polls.lock().unwrap().get(&id).cloned()) {
+        Some((_, subscriber, user_id)) if user_id == owner(caller) => subscriber,
+        _ => return Reply::error(404, format!("no subscription {}", id)),
+    };
+
+    let params = query_params(query);
+    let timeout = match params.iter().find(|(name, _)| name == "timeout") {
+        Some((_, text)) => match text.parse::<u64>() {
+            Ok(ms) => Duration::from_millis(ms),
+            Err(_) => return Reply::error(400, format!("bad timeout {:?}", text)),
+        },
+        None => DEFAULT_POLL_TIMEOUT,
+    };
+
+    Reply::json(200, serde_json::to_string(&subscriber.poll(timeout.min(max_timeout))).unwrap())
+}
+
+fn unsubscribe(graph: &DataFlowGraph, polls: &LongPolls, id: &str, caller: &Option<Principal>) -> Reply {
+    let removed = id.parse::<u64>().ok().and_then(|id| {
+        let mut polls = polls.lock().unwrap();
+
+        match polls.get(&id) {
+            Some((_, _, user_id)) if *user_id == owner(caller) => polls.remove(&id).map(|entry| (id, entry)),
+            _ => None,
+        }
+    });
+
+    match removed {
+        Some((id, (leaf_index, subscriber, _))) => {
+            subscriber.close();
+            graph.unsubscribe(leaf_index, id);
+            Reply::json(204, String::new())
+        },
+        None => Reply::error(404, format!("no subscription {}", id)),
+    }
+}
+
+fn owner(caller: &Option<Principal>) -> Option<String> {
+    caller.as_ref().map(|principal| principal.user_id.clone())
+}
+
+/// Splits a URL query into decoded (name, value) pairs, in order and repeats kept
+pub(crate) fn query_params(query: &str) -> Vec<(String, String)> {
+    query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.find('=') {
+            Some(pos) => (decode(&pair[..pos]), decode(&pair[pos + 1..])),
+            None => (decode(pair), String::new()),
+        })
+        .collect()
+}
+
+//percent decoding for paths and query values, + is a space
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let hex = |byte: u8| (byte as char).to_digit(16).map(|digit| digit as u8);
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(high), Some(low)) => {
+                    decoded.push(high * 16 + low);
+                    i += 2;
+                },
+                _ => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::{SocketAddr, TcpStream};
+    use std::time::Instant;
+
+    const LIMITS: HttpLimits = HttpLimits { max_requests: 16, max_body_size: 1024, max_poll_timeout: Duration::from_secs(1) };
+
+    fn start(auth: Authenticator, limits: HttpLimits) -> SocketAddr {
+        let server = bind("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let graph = Arc::new(RwLock::new(crate::build_server_graph()));
+
+        spawn(move || serve(server, graph, Arc::new(auth), QueueConfig::default(), limits));
+
+        addr
+    }
+
+    //a principal that can read the left Leaf and write Stories, behind the token "writer"
+    fn writer_auth() -> Authenticator {
+        let grant = Grant::new(&["/latencytestleft"], &["Stories"]);
+        let principal = Principal { user_id: "writer".to_owned(), grant: Some(grant) };
+
+        Authenticator::new([("writer".to_owned(), principal)].iter().cloned().collect(), None)
+    }
+
+    //sends one request and returns the status and body, extra_headers end in \r\n
+    fn send(addr: SocketAddr, method: &str, path: &str, extra_headers: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}", method, path, extra_headers).unwrap();
+
+        if !extra_headers.contains("Transfer-Encoding") {
+            write!(stream, "Content-Length: {}\r\n", body.len()).unwrap();
+        }
+
+        write!(stream, "\r\n{}", body).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").map_or("", |(_, body)| body);
+
+        (status, body.to_owned())
+    }
+
+    fn story(author: i32, story: i32) -> String {
+        format!(r#"{{"root_id": "Stories", "changes": [{{"typing": "Insertion", "batch": [{{"data": [{{"t": "Int", "c": {}}}, {{"t": "Int", "c": {}}}]}}]}}]}}"#,
+            author, story)
+    }
+
+    #[test]
+    fn routes_answer_with_their_status() {
+        let addr = start(Authenticator::default(), LIMITS);
+
+        assert_eq!(send(addr, "GET", "/metrics", "", "").0, 200);
+        assert_eq!(send(addr, "GET", "/views/latencytestleft?key=7", "", ""), (200, "[]".to_owned()));
+        assert_eq!(send(addr, "GET", "/views/latencytestleft?index=StoryID&limit=2", "", ""), (200, "[]".to_owned()));
+        assert_eq!(send(addr, "GET", "/views/latencytestleft", "", "").0, 400);
+        assert_eq!(send(addr, "GET", "/views/latencytestleft?key=x", "", "").0, 400);
+        assert_eq!(send(addr, "GET", "/views/nowhere?key=7", "", "").0, 404);
+        assert_eq!(send(addr, "PUT", "/views/latencytestleft", "", "").0, 405);
+        assert_eq!(send(addr, "GET", "/subscriptions/12345", "", "").0, 404);
+        assert_eq!(send(addr, "GET", "/nothing/here", "", "").0, 404);
+
+        //without authentication there's no grant to check a write against
+        assert_eq!(send(addr, "POST", "/roots/Stories", "", &story(1, 7)).0, 403);
+    }
+
+    #[test]
+    fn writes_show_up_in_reads() {
+        let addr = start(writer_auth(), LIMITS);
+        let token = "Authorization: Bearer writer\r\n";
+
+        assert_eq!(send(addr, "POST", "/roots/Stories", token, &story(1, 7)).0, 204);
+        assert_eq!(send(addr, "POST", "/roots/Stories", token, "not json").0, 400);
+        assert_eq!(send(addr, "POST", "/roots/Stories", token, &story(1, 7).replace("\"Stories\"", "\"Votes\"")).0, 400);
+
+        let (status, body) = send(addr, "GET", "/views/latencytestleft?key=7&token=writer", "", "");
+        assert_eq!(status, 200);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap()[0]["data"][1]["c"], 7);
+    }
+
+    #[test]
+    fn missing_or_bad_tokens_get_a_401_and_ungranted_paths_a_403() {
+        let addr = start(writer_auth(), LIMITS);
+
+        assert_eq!(send(addr, "GET", "/views/latencytestleft?key=7", "", "").0, 401);
+        assert_eq!(send(addr, "GET", "/views/latencytestleft?key=7", "Authorization: Bearer nobody\r\n", "").0, 401);
+        assert_eq!(send(addr, "GET", "/metrics", "", "").0, 401);
+
+        assert_eq!(send(addr, "GET", "/views/latencytestright?key=7&token=writer", "", "").0, 403);
+        assert_eq!(send(addr, "POST", "/subscriptions/latencytestright?token=writer", "", "").0, 403);
+        assert_eq!(send(addr, "POST", "/roots/Votes?token=writer", "", &story(1, 7)).0, 403);
+    }
+
+    #[test]
+    fn bodies_past_the_limit_get_a_413() {
+        let addr = start(writer_auth(), HttpLimits { max_body_size: 16, ..LIMITS });
+        let token = "Authorization: Bearer writer\r\n";
+
+        assert_eq!(send(addr, "POST", "/roots/Stories", token, &story(1, 7)).0, 413);
+
+        //without a Content-Length the body is only read up to the limit
+        let chunked = format!("{}Transfer-Encoding: chunked\r\n", token);
+        let body = story(1, 7);
+        assert_eq!(send(addr, "POST", "/roots/Stories", &chunked, &format!("{:x}\r\n{}\r\n0\r\n\r\n", body.len(), body)).0, 413);
+    }
+
+    #[test]
+    fn long_polls_wait_no_longer_than_the_limit() {
+        let addr = start(writer_auth(), HttpLimits { max_poll_timeout: Duration::from_millis(200), ..LIMITS });
+
+        let (status, body) = send(addr, "POST", "/subscriptions/latencytestleft?token=writer", "", "");
+        assert_eq!(status, 200);
+        let id = serde_json::from_str::<serde_json::Value>(&body).unwrap()["subscription_id"].as_u64().unwrap();
+        let poll = format!("/subscriptions/{}?timeout=60000&token=writer", id);
+
+        //the snapshot is waiting, then nothing is until the write
+        assert!(send(addr, "GET", &poll, "", "").1.contains("\"snapshot\":true"));
+
+        let started = Instant::now();
+        assert_eq!(send(addr, "GET", &poll, "", ""), (200, "[]".to_owned()));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        //a waiting poll holds no lock on the graph, so a write gets through to it
+        let waiting = spawn(move || send(addr, "GET", &poll, "", "").1);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(send(addr, "POST", "/roots/Stories?token=writer", "", &story(2, 8)).0, 204);
+        assert!(waiting.join().unwrap().contains("\"c\":8"));
+
+        assert_eq!(send(addr, "DELETE", &format!("/subscriptions/{}?token=writer", id), "", "").0, 204);
+        assert_eq!(send(addr, "DELETE", &format!("/subscriptions/{}?token=writer", id), "", "").0, 404);
+    }
+
+    #[test]
+    fn query_params_are_split_and_decoded() {
+        let pairs = |list: &[(&str, &str)]| list.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect::<Vec<_>>();
+
+        assert_eq!(query_params("key=1&key=a%20b&flag&&x=1+2"), pairs(&[("key", "1"), ("key", "a b"), ("flag", ""), ("x", "1 2")]));
+        assert_eq!(query_params("a%3Db=c%26d"), pairs(&[("a=b", "c&d")]));
+        assert!(query_params("").is_empty());
+    }
+
+    #[test]
+    fn decode_leaves_bad_escapes_alone() {
+        assert_eq!(decode("%2Fstories"), "/stories");
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz"), "%zz");
+        assert_eq!(decode("%E2%9C%93"), "\u{2713}");
+    }
+}
@@ -5,15 +5,30 @@ extern crate serde_derive;
 
 use std::net::TcpListener;
 use std::thread::spawn;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
-use tungstenite::Message;
-use tungstenite::Message::Text;
+use clap::Parser;
+use futures::SinkExt;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tungstenite::accept_hdr;
-use tungstenite::handshake::server::{Request, Response};
-
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tokio_tungstenite::accept_hdr_async_with_config;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message as AsyncMessage;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+
+pub mod auth;
+pub mod config;
+pub mod connection;
+pub mod httpserver;
 pub mod operators;
+pub mod readiness;
+pub mod subscribers;
+pub mod tls;
 pub mod types;
 pub mod units;
 pub mod viewsandgraphs;
@@ -22,11 +37,22 @@ use crate::types::operatortype::OperatorType;
 use crate::types::operatortype::OperatorType::{A, I, L, P, R, S};
 use crate::viewsandgraphs::dfg::DataFlowGraph;
 use crate::viewsandgraphs::serverinfo::ServerInfo;
-use crate::units::serverchange::ServerChange;
-use crate::operators::operation::Operation::Leafor;
+use crate::units::servererror::ServerError;
+use crate::units::subscription::{Resume, SubscribeResponse, PATH_SUBSCRIPTION_REQUEST_ID};
+use crate::auth::Authenticator;
+use crate::config::Config;
+use crate::connection::Connection;
+use crate::readiness::ReadinessGate;
+use crate::subscribers::channel::QueueConfig;
+use crate::operators::leaf::PRIMARY_INDEX;
+use crate::types::datatype::DataType;
 use crate::types::changetype::ChangeType;
 use crate::types::permissiontype::PermissionType;
 use crate::units::change::Change;
+use crate::units::grant::Grant;
+
+//how long a client gets to finish the TLS and websocket handshakes, one that stalls gives its slot back after it
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 
 
 // SOME NOTES
@@ -42,11 +68,16 @@ fn build_server_graph() -> DataFlowGraph {
 
     let stories_root = r##"{
         "root_id": "Stories",
-        "key_index": 1
+        "key_index": 1,
+        "column_names": ["AuthorUserID", "StoryID"],
+        "schema": ["Int", "Int"]
     }"##;
     let votes_root = r##"{
         "root_id": "Votes",
-        "key_index": 1
+        "key_index": 1,
+        "multi_row": true,
+        "column_names": ["StoryID", "VoterUserID"],
+        "schema": ["Int", "Int"]
     }"##;
     let aggregator = r##"{
         "group_by_col": [0]
@@ -55,23 +86,31 @@ fn build_server_graph() -> DataFlowGraph {
     graph.add_node(OperatorType::R, stories_root.to_owned());
     graph.add_node(OperatorType::R, votes_root.to_owned());
     graph.add_node(OperatorType::A, aggregator.to_owned());
-    graph.add_leaf("JoinLeft".to_owned(), 1, "/latencytestleft".to_string());
-    graph.add_leaf("JoinRight".to_owned(), 0, "/latencytestright".to_string());
+    graph.add_leaf("JoinLeft".to_owned(), 1, false, "/latencytestleft".to_string());
+    graph.add_leaf("JoinRight".to_owned(), 0, false, "/latencytestright".to_string());
 
     graph.add_edge(0, 3);
     graph.add_edge(1, 2);
     graph.add_edge(2, 4);
 
+    //stories by author, alongside the primary stories by id, and in id order for paging feeds
+    graph.add_index(3, "AuthorUserID".to_owned(), 0, false);
+    graph.add_index(3, "StoryID".to_owned(), 1, true);
+
     // let stories_root = r##"{
     //     "root_id": "OnlyServer",
-    //     "key_index": 0
+    //     "key_index": 0,
+    //     "column_names": ["Author", "Votes"],
+    //     "schema": ["Text", "Int"]
     // }"##;
 
     // graph.add_node(OperatorType::R, stories_root.to_owned());
-    // graph.add_leaf("Only".to_owned(), 0, "/dummytest".to_string());
+    // graph.add_leaf("Only".to_owned(), 0, false, "/dummytest".to_string());
 
     // graph.add_edge(0, 1);
 
+    graph.build_schemas().unwrap();
+
     graph
 }
 
@@ -104,8 +143,6 @@ fn build_server_info() -> ServerInfo {
                     "c": {
                         "mat_view": {
                             "name": "Users and VoteCounts",
-                            "column_names": ["AuthorUserID", "StoryID", "StoryVoteCount"],
-                            "schema": ["Int", "Int", "Int"],
                             "key_index": 1
                         }
                     }
@@ -154,117 +191,262 @@ fn build_server_info() -> ServerInfo {
     info.add_path("/latencytestright".to_owned(), "".to_owned());
     info.add_path("/dummytest".to_owned(), dummy_test_subgraph.to_owned());
 
-    info.add_permission("/dummytest".to_string(), PermissionType::Write);
-    info.add_permission("/dummytestread".to_string(), PermissionType::Read);
-    info.add_permission("/latencytestleft".to_string(), PermissionType::Write);
-    info.add_permission("/latencytestright".to_string(), PermissionType::Write);
-    info.add_permission("/latencytestread".to_string(), PermissionType::Read);
+    info.add_permission("/dummytest".to_string(), PermissionType::Read);
+    info.add_permission("/dummytestread".to_string(), PermissionType::Write);
+    info.add_permission("/latencytestleft".to_string(), PermissionType::Read);
+    info.add_permission("/latencytestright".to_string(), PermissionType::Read);
+    info.add_permission("/latencytestread".to_string(), PermissionType::Write);
+
+    //what each path may touch, every message on a connection is checked against its path's grant
+    info.add_grant("/dummytest".to_string(), Grant::new(&["/dummytest"], &[]));
+    info.add_grant("/dummytestread".to_string(), Grant::new(&[], &["Stories", "Votes"]));
+    info.add_grant("/latencytestleft".to_string(), Grant::new(&["/latencytestleft"], &[]));
+    info.add_grant("/latencytestright".to_string(), Grant::new(&["/latencytestright"], &[]));
+    info.add_grant("/latencytestread".to_string(), Grant::new(&["/latencytestleft", "/latencytestright"], &["Stories", "Votes"]));
 
     info
 }
 
 fn main() {
-    let graph = Arc::new(Mutex::new(build_server_graph()));
-    let counter = Arc::new(Mutex::new(0));
-    //let info = Arc::new(Mutex::new(build_server_info()));
+    let config = Config::parse();
+
+    env_logger::Builder::new().parse_filters(&config.log_level).init();
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+
+    if let Some(workers) = config.workers {
+        runtime.worker_threads(workers);
+    }
+
+    or_exit(runtime.build().map_err(|err| format!("can't start the runtime: {}", err))).block_on(serve(config));
+}
+
+//startup can't go on without what result holds, so an error is printed and the process exits
+fn or_exit<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("noria-server: {}", err);
+        std::process::exit(1);
+    })
+}
+
+//builds the graph, starts the HTTP server and serves websocket clients until the process is stopped
+async fn serve(config: Config) {
+    let server_graph = build_server_graph();
+
+    //retried writes are recognised by their client_id and write_id for the dedup window
+    server_graph.set_dedup_window(config.dedup_window());
+
+    let queue_config = config.queue_config();
+
+    //client subgraph mat_views without a hand-written schema get one from the server graph
+    let mut server_info = build_server_info();
+    server_info.fill_schemas(&server_graph).unwrap();
+
+    //structure is fixed once built, writes and reads only need the read lock, nodes lock themselves
+    let graph = Arc::new(RwLock::new(server_graph));
+
+    //benchmark participants hold writers back until that many have connected, for latency runs,
+    //the timeout stops them waiting forever, without it writers start right away
+    let gate = config.benchmark_participants.map(|participants| Arc::new(ReadinessGate::new(participants, config.benchmark_timeout())));
+    let info = Arc::new(server_info);
+
+    //a tokens file is a key store of token to principal, an HMAC secret accepts signed tokens,
+    //with neither anyone who can reach the ports can read and write whatever their path allows
+    let tokens = match &config.auth_tokens_file {
+        Some(path) => or_exit(Authenticator::load_tokens(path)),
+        None => HashMap::new(),
+    };
+    let auth = Arc::new(Authenticator::new(tokens, config.auth_hmac_secret.clone().map(String::into_bytes)));
+
+    if !auth.enabled() {
+        warn!("no auth tokens file or HMAC secret, clients are not authenticated");
+    }
+
+    //plain HTTP alongside the websockets, for curl and jobs that can't speak tungstenite
+    let http_server = or_exit(httpserver::bind(&config.http_addr()));
+    let http_graph = Arc::clone(&graph);
+    let http_auth = Arc::clone(&auth);
+    let http_limits = config.http_limits();
+    spawn(move || httpserver::serve(http_server, http_graph, http_auth, queue_config, http_limits));
+
+    //a certificate and key, PEM files, serve wss:// instead of ws:// so HTTPS pages can connect
+    let tls = match (&config.tls_cert_file, &config.tls_key_file) {
+        (Some(cert_path), Some(key_path)) => Some(or_exit(tls::acceptor(cert_path, key_path))),
+        _ => None,
+    };
+
+    let ws_config = WebSocketConfig { max_message_size: Some(config.max_message_size), ..WebSocketConfig::default() };
+    let connections = Arc::new(Semaphore::new(config.max_connections));
 
     println!("creating websocket");
-    env_logger::init();
-    let server = TcpListener::bind("127.0.0.1:3012").unwrap();
+    let ws_addr = config.ws_addr();
+    let server = or_exit(tokio::net::TcpListener::bind(&ws_addr).await.map_err(|err| format!("can't bind websockets on {}: {}", ws_addr, err)));
+
+    //one task per connection, Leafs only ever queue changes for it so a slow client holds up no one else
+    loop {
+        let stream = match server.accept().await {
+            Ok((stream, _)) => stream,
+            Err(_) => continue,
+        };
+
+        //past max connections a client is closed before the handshake, it can retry later
+        let permit = match Arc::clone(&connections).try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                warn!("at {} connections, turning a client away", config.max_connections);
+                continue;
+            },
+        };
 
-    for stream in server.incoming() {
-        println!("server loop");
         let graph_ref = Arc::clone(&graph);
-        let counter_ref = Arc::clone(&counter);
-        //let info_ref = Arc::clone(&info);
-        println!("resources cloned");
-
-        spawn( move || {
-            println!("thread started");
-            //let mut i = info_ref.lock().unwrap();
-            println!("resource accessed");
-            let mut path: String = "".to_string();
-
-            let callback = |req: &Request, mut response: Response| {
-                println!("Received a new ws handshake");
-                println!("The request's path is: {}", req.uri().path());
-                path = req.uri().path().to_string();
-
-                // println!("The request's headers are:");
-                // for (ref header, _value) in req.headers() {
-                //     println!("* {}", header);
-                // }
-
-                // Let's add an additional header to our response to the client.
-                let headers = response.headers_mut();
-                headers.append("MyCustomHeader", ":)".parse().unwrap());
-                headers.append("SOME_TUNGSTENITE_HEADER", "header_value".parse().unwrap());
-
-                Ok(response)
-            };
+        let gate_ref = gate.clone();
+        let auth_ref = Arc::clone(&auth);
+        let info_ref = Arc::clone(&info);
+        let tls_ref = tls.clone();
+
+        //the TLS handshake happens on the connection's own task, a slow one holds up no other accepts
+        tokio::spawn(async move {
+            match tls_ref {
+                Some(acceptor) => match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => serve_client(stream, graph_ref, gate_ref, auth_ref, info_ref, queue_config, ws_config).await,
+                    Ok(Err(err)) => warn!("TLS handshake failed: {}", err),
+                    Err(_) => warn!("TLS handshake timed out"),
+                },
+                None => serve_client(stream, graph_ref, gate_ref, auth_ref, info_ref, queue_config, ws_config).await,
+            }
 
-            let info = build_server_info();
-            let mut websocket = accept_hdr(stream.unwrap(), callback).unwrap();
-            let permission = info.path_permission_map.get(&path).unwrap();
-            
-            match permission {
-                PermissionType::Write => {
-                    let mut g = graph_ref.lock().unwrap();
-                    let client_subgraph = info.path_subgraph_map.get(&path).unwrap();
-                    let ws_leaf_ni = g.path_leaf_map.get(&path).unwrap();
-                    
-                    let graph_msg = Message::text(client_subgraph);
-                    websocket.write_message(graph_msg).unwrap();
-                    println!("Sending initial graph");
+            drop(permit);
+        });
+    }
+}
+
+//handshake, the initial graph or gate, then the connection itself, over plain TCP or TLS
+async fn serve_client<S>(stream: S, graph_ref: Arc<RwLock<DataFlowGraph>>, gate_ref: Option<Arc<ReadinessGate>>,
+    auth_ref: Arc<Authenticator>, info_ref: Arc<ServerInfo>, queue_config: QueueConfig, ws_config: WebSocketConfig)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut path: String = "".to_string();
+    let mut query: String = "".to_string();
+    let mut principal = None;
+    let info = &*info_ref;
+
+    //unknown paths and bad tokens are turned away before the upgrade, with a plain HTTP status
+    let callback = |req: &Request, response: Response| {
+        debug!("websocket handshake for {}", req.uri().path());
+        path = req.uri().path().to_string();
+        query = req.uri().query().unwrap_or("").to_string();
+
+        if !info.path_permission_map.contains_key(&path) {
+            return Err(reject(404, format!("no path {}", path)));
+        }
+
+        let authorization = req.headers().get("Authorization").and_then(|value| value.to_str().ok());
+
+        match auth_ref.caller(authorization, &httpserver::query_params(&query)) {
+            Ok(found) => principal = found,
+            Err(err) => return Err(reject(401, err)),
+        }
+
+        Ok(response)
+    };
+
+    let mut websocket = match timeout(HANDSHAKE_TIMEOUT, accept_hdr_async_with_config(stream, callback, Some(ws_config))).await {
+        Ok(Ok(websocket)) => websocket,
+        _ => return,
+    };
+    let permission = info.path_permission_map.get(&path).unwrap();
+    let grant = match &principal {
+        Some(principal) => principal.effective_grant(&info.grant(&path)),
+        None => info.grant(&path),
+    };
+    let mut connection = Connection::new(queue_config, grant, principal);
     
-                    let mut leaf_op = g.data.node_weight(*ws_leaf_ni).unwrap().write().unwrap();
+    match permission {
+        //readers get the client subgraph and a subscription to the path's Leaf
+        PermissionType::Read => {
+            let client_subgraph = info.path_subgraph_map.get(&path).unwrap();
+
+            let parsed = {
+                let g = graph_ref.read().unwrap();
+                let ws_leaf_ni = g.path_leaf_map.get(&path).unwrap();
+
+                //?key=17&key=42 subscribes to only those keys of the leaf, no keys is all of it
+                //?since=N&epoch=E resumes after the last seq the client saw before reconnecting
+                let params = httpserver::query_params(&query);
+                let key_params: Vec<&String> = params.iter()
+                    .filter(|(name, _)| name == "key")
+                    .map(|(_, value)| value)
+                    .collect();
+                let keys = if key_params.is_empty() {
+                    Ok(None)
+                } else {
+                    key_params.iter()
+                        .map(|text| g.parse_key(ws_leaf_ni.index(), PRIMARY_INDEX, text))
+                        .collect::<Result<HashSet<DataType>, String>>()
+                        .map(Some)
+                };
+                let since = Resume::from_query(&params);
 
-                    if let Leafor(leaf) = &mut*leaf_op {
-                        leaf.initial_connect(websocket);
-                    }
-                },
-                PermissionType::Read => {
-                    {
-                        let mut c = counter_ref.lock().unwrap();
-                        *c += 1; 
-                    }
+                keys.and_then(|keys| since.map(|since| (keys, since)))
+            };
 
-                    loop {
-                        let mut c = counter_ref.lock().unwrap();
+            let (keys, since) = match parsed {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    let msg = AsyncMessage::text(serde_json::to_string(&ServerError::new("".to_owned(), err)).unwrap());
+                    websocket.send(msg).await.unwrap_or(());
+                    websocket.close(None).await.unwrap_or(());
+                    return;
+                },
+            };
+            
+            let graph_msg = AsyncMessage::text(client_subgraph);
+            if websocket.send(graph_msg).await.is_err() {
+                return;
+            }
+            trace!("sent the client subgraph for {}", path);
+
+            //the leaf the client connected to is just its first subscription, more can be added later,
+            //it's answered like one the client asked for, so the client learns its id to unsubscribe with,
+            //its lock can be held by a write blocked on a full queue, so off the runtime
+            tokio::task::block_in_place(|| {
+                let subscribed = {
+                    let g = graph_ref.read().unwrap();
+                    connection.subscribe(&g, &path, keys, since)
+                };
 
-                        if (*c >= 6) {
-                            break;
-                        }
-                    }
+                connection.send(serde_json::to_string(&SubscribeResponse::new(PATH_SUBSCRIPTION_REQUEST_ID, subscribed)).unwrap());
+            });
+        },
+        //writers send ServerChanges, to the Roots their grant allows
+        PermissionType::Write => {
+            //in benchmark mode writers all start together once enough of them are connected
+            if let Some(gate) = gate_ref {
+                if gate.arrive().await {
+                    debug!("readiness gate open, writer on {} starting", path);
+                } else {
+                    warn!("readiness gate timed out, starting without every participant");
+                }
+            }
 
-                    println!("limit break!");
-
-                    let msg = Message::text(serde_json::to_string("").unwrap());
-                    websocket.write_message(msg).unwrap();
-
-                    loop {
-                        let msg = websocket.read_message();
-
-                        match msg {
-                            Err(err) => {
-                                websocket.close(None);
-                                break;
-                            }
-                            Ok(message) => {
-                                if message.is_binary() || message.is_text() {
-                                    if let Text(inner_json) = message {
-                                        let mut g = graph_ref.lock().unwrap();
-                                        let sc: ServerChange = serde_json::from_str(&inner_json).unwrap();
-                                        g.change_to_root(sc.root_id, sc.changes);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                },            
+            let msg = AsyncMessage::text(serde_json::to_string("").unwrap());
+            if websocket.send(msg).await.is_err() {
+                return;
             }
-        });
+        },            
     }
+
+    connection.run(websocket, &graph_ref).await;
+}
+
+//an HTTP error reply for a refused websocket handshake
+fn reject(status: u16, error: String) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(serde_json::json!({ "error": error }).to_string()));
+    *response.status_mut() = StatusCode::from_u16(status).unwrap();
+
+    response
 }
 
 fn dummymain() {
@@ -298,4 +480,150 @@ fn dummymain() {
                 }
             });
         }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use futures::StreamExt;
+    use tokio_rustls::rustls::{Certificate, ClientConfig, RootCertStore, ServerName};
+    use tokio_rustls::TlsConnector;
+    use tokio_tungstenite::WebSocketStream;
+    use crate::units::clientmessage::ClientMessage;
+    use crate::units::row::Row;
+    use crate::units::serverchange::ServerChange;
+    use crate::units::subscription::UnsubscribeRequest;
+    use crate::units::writeack::WriteAck;
+
+    //serves plain websocket clients on the test graph, the Stories Leaf is node 3
+    async fn plain_server(gate: Option<Arc<ReadinessGate>>) -> (std::net::SocketAddr, Arc<RwLock<DataFlowGraph>>) {
+        let server_graph = build_server_graph();
+        let mut server_info = build_server_info();
+        server_info.fill_schemas(&server_graph).unwrap();
+        let graph = Arc::new(RwLock::new(server_graph));
+        let info = Arc::new(server_info);
+        let auth = Arc::new(Authenticator::new(HashMap::new(), None));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let served = Arc::clone(&graph);
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let client = serve_client(stream, Arc::clone(&served), gate.clone(), Arc::clone(&auth), Arc::clone(&info),
+                    QueueConfig::default(), WebSocketConfig::default());
+                tokio::spawn(client);
+            }
+        });
+
+        (addr, graph)
+    }
+
+    async fn connect(addr: std::net::SocketAddr, path: &str) -> WebSocketStream<tokio::net::TcpStream> {
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+
+        tokio_tungstenite::client_async(format!("ws://localhost{}", path), stream).await.unwrap().0
+    }
+
+    async fn next_text(websocket: &mut WebSocketStream<tokio::net::TcpStream>) -> String {
+        let message = timeout(HANDSHAKE_TIMEOUT, websocket.next()).await.unwrap().unwrap().unwrap();
+
+        message.to_text().unwrap().to_owned()
+    }
+
+    fn story_write(request_id: u64, story_id: i32) -> AsyncMessage {
+        let row = Row::new(vec![DataType::Int(1), DataType::Int(story_id)]);
+        let mut sc = ServerChange::new("Stories".to_owned(), vec![Change::new(ChangeType::Insertion, vec![row])]);
+        sc.request_id = Some(request_id);
+
+        AsyncMessage::text(serde_json::to_string(&ClientMessage::Write(sc)).unwrap())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serves_wss_with_a_self_signed_cert() {
+        let _counting = connection::CONNECTED_TESTS.lock().await;
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("cert.pem");
+        let key_path = dir.path().join("key.pem");
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+        let acceptor = tls::acceptor(cert_path.to_str().unwrap(), key_path.to_str().unwrap()).unwrap();
+
+        let server_graph = build_server_graph();
+        let mut server_info = build_server_info();
+        server_info.fill_schemas(&server_graph).unwrap();
+        let graph = Arc::new(RwLock::new(server_graph));
+        let info = Arc::new(server_info);
+        let auth = Arc::new(Authenticator::new(HashMap::new(), None));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let stream = acceptor.accept(stream).await.unwrap();
+
+            serve_client(stream, graph, None, auth, info, QueueConfig::default(), WebSocketConfig::default()).await;
+        });
+
+        //the client only trusts the generated certificate
+        let mut roots = RootCertStore::empty();
+        roots.add(&Certificate(cert.serialize_der().unwrap())).unwrap();
+        let client_config = ClientConfig::builder().with_safe_defaults().with_root_certificates(roots).with_no_client_auth();
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let stream = TlsConnector::from(Arc::new(client_config))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+            .unwrap();
+
+        let (mut websocket, response) = tokio_tungstenite::client_async("wss://localhost/latencytestleft", stream).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+        //a reader's first message is its client subgraph
+        let first = timeout(HANDSHAKE_TIMEOUT, websocket.next()).await.unwrap().unwrap().unwrap();
+        assert!(first.to_text().unwrap().contains("\"operators\""));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn gated_writers_are_held_until_the_gate_opens() {
+        let _counting = connection::CONNECTED_TESTS.lock().await;
+        let gate = Arc::new(ReadinessGate::new(2, readiness::DEFAULT_GATE_TIMEOUT));
+        let (addr, graph) = plain_server(Some(gate)).await;
+
+        let mut first = connect(addr, "/dummytestread").await;
+        first.send(story_write(1, 7)).await.unwrap();
+
+        //alone behind the gate, the writer hears nothing and its write waits
+        assert!(timeout(Duration::from_millis(200), first.next()).await.is_err());
+        assert!(graph.read().unwrap().lookup(3, PRIMARY_INDEX, &DataType::Int(7), None).unwrap().is_empty());
+
+        let mut second = connect(addr, "/dummytestread").await;
+        assert_eq!(next_text(&mut second).await, "\"\"");
+        assert_eq!(next_text(&mut first).await, "\"\"");
+
+        let ack: WriteAck = serde_json::from_str(&next_text(&mut first).await).unwrap();
+        assert_eq!(ack, WriteAck::new(1, "Stories".to_owned(), Ok(())));
+        assert_eq!(graph.read().unwrap().lookup(3, PRIMARY_INDEX, &DataType::Int(7), None).unwrap().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn path_subscription_is_answered_with_its_id() {
+        let _counting = connection::CONNECTED_TESTS.lock().await;
+        let (addr, _graph) = plain_server(None).await;
+        let mut reader = connect(addr, "/latencytestleft").await;
+
+        assert!(next_text(&mut reader).await.contains("\"operators\""));
+        let snapshot: ServerChange = serde_json::from_str(&next_text(&mut reader).await).unwrap();
+        let response: SubscribeResponse = serde_json::from_str(&next_text(&mut reader).await).unwrap();
+        assert_eq!(response.request_id, PATH_SUBSCRIPTION_REQUEST_ID);
+        assert!(response.subscription_id.is_some());
+        assert_eq!(response.subscription_id, snapshot.subscription_id);
+
+        //the id is the one to unsubscribe with
+        let request = UnsubscribeRequest { request_id: 1, subscription_id: response.subscription_id.unwrap() };
+        reader.send(AsyncMessage::text(serde_json::to_string(&ClientMessage::Unsubscribe(request)).unwrap())).await.unwrap();
+        let response: SubscribeResponse = serde_json::from_str(&next_text(&mut reader).await).unwrap();
+        assert_eq!((response.request_id, response.error), (1, None));
+    }
+}
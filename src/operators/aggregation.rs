@@ -2,6 +2,9 @@ use crate::units::row::Row;
 use crate::units::change::Change;
 use crate::types::changetype::ChangeType;
 use crate::types::datatype::DataType;
+use crate::types::functype::FuncType;
+use crate::types::schematype::SchemaType;
+use crate::units::schema::Schema;
 use crate::operators::Operator;
 
 use std::collections::HashMap;
@@ -10,133 +13,292 @@ fn return_hash_a() -> HashMap<Vec<DataType>, Row> {
     HashMap::new()
 }
 
+fn return_hash_t() -> HashMap<Vec<DataType>, Totals> {
+    HashMap::new()
+}
+
+fn return_count() -> FuncType {
+    FuncType::COUNT
+}
+
+//running totals for a group
+//rows counts every row, values only the non NULL ones SUM and AVG see
+//poisoned: the sum overflowed or met a mismatched type, SUM and AVG stay NULL until the group empties
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+struct Totals {
+    rows: i32,
+    values: i64,
+    sum: DataType,
+    #[serde(default)]
+    poisoned: bool,
+}
+
 //Aggregation Operator
 //group_by_col is ordered lowest to highest
+//function defaults to COUNT, output rows are the group by columns followed by the aggregate
 #[derive(Debug, Clone)]
 #[derive(Serialize, Deserialize)]
 pub struct Aggregation {
     group_by_col: Vec<usize>,
-    //function: FuncType,
+    #[serde(default = "return_count")]
+    function: FuncType,
     #[serde(default = "return_hash_a")]
     state: HashMap<Vec<DataType>, Row>,
+    #[serde(default = "return_hash_t")]
+    totals: HashMap<Vec<DataType>, Totals>,
 }
 
 //Operator Trait for Aggregation
-//implements hard coded length for count, no sum or func matching yet
-//also does not group changes first, which would be a lot cleaner, but harder to implement
+//does not group changes first, which would be a lot cleaner, but harder to implement
 impl Operator for Aggregation {
     fn apply(&mut self, prev_change_vec: Vec<Change>) -> Vec<Change> {
         let mut next_change_vec = Vec::new();
 
         //multiple Insertions and Deletions
         for change in prev_change_vec {
-            match change.typing {
-                ChangeType::Insertion => {
-                    //multiple rows in a single Change
-                    for row in &(change.batch) {
-                        //form key to access aggregates in state
-                        let mut temp_key = Vec::new();
-                        
-                        for index in &self.group_by_col {
-                            temp_key.push(row.data[*index].clone());
-                        } 
-
-                        match self.state.get_mut(&temp_key) {
-                            None => {
-                                //create new row to insert with only the group by columns
-                                let mut new_row_vec = Vec::new();
-
-                                for index in &self.group_by_col {
-                                    new_row_vec.push(row.data[*index].clone());
-                                } 
-
-                                //copy for key in hashmap
-                                let new_row_key = new_row_vec.clone();
-
-                                //since its a new key, gets its own count
-                                new_row_vec.push(DataType::Int(1));
-
-                                let new_row = Row::new(new_row_vec);
-
-                                //apply changes to operator's internal state
-                                self.state.insert(new_row_key, new_row.clone());
-
-                                let mut change_rows = Vec::new();
-                                change_rows.push(new_row.clone());
-                            
-                                //send insertion change downstream
-                                let new_group_change = Change::new(ChangeType::Insertion, change_rows);
-                                next_change_vec.push(new_group_change); 
-                            },
-                            Some(row_to_incr) => {
-                                //sends deletion change downstream
-                                let mut change_rows_del = Vec::new();
-                                change_rows_del.push(row_to_incr.clone());
-
-                                let delete_old = Change::new(ChangeType::Deletion, change_rows_del);
-                                next_change_vec.push(delete_old);
-
-                                //increments count in state
-                                let len = &row_to_incr.data.len();
-                                let new_count = match &row_to_incr.data[len - 1] {
-                                    DataType::Int(count) => count + 1,
-                                    _ => 0,
-                                };
-                                row_to_incr.data[len - 1] = DataType::Int(new_count);
-
-                                //sends insertion change downstream
-                                let mut change_rows_ins = Vec::new();
-                                change_rows_ins.push(row_to_incr.clone());
-
-                                let insert_new = Change::new(ChangeType::Insertion, change_rows_ins);
-                                next_change_vec.push(insert_new);
-                            },
-                        }
-                    }
+            //multiple rows in a single Change
+            for row in &(change.batch) {
+                //form key to access aggregates in state
+                let mut temp_key = Vec::new();
+
+                for index in &self.group_by_col {
+                    temp_key.push(row.data[*index].clone());
                 }
+
                 //In this model, we assume that deletions will always match with one aggregated row
-                ChangeType::Deletion => {
-                    //multiple rows in a single Change
-                    for row in &(change.batch) {
-                        let mut temp_key = Vec::new();
-                        
-                        for index in &self.group_by_col {
-                            temp_key.push(row.data[*index].clone());
-                        } 
-
-                        match self.state.get_mut(&temp_key) {
-                            Some(row_to_decr) => {
-                                //sends deletion change downstream
-                                let mut change_rows_del = Vec::new();
-                                change_rows_del.push(row_to_decr.clone());
-
-                                let delete_old = Change::new(ChangeType::Deletion, change_rows_del);
-                                next_change_vec.push(delete_old);
-
-                                //decrements count in state
-                                let len = &row_to_decr.data.len();
-                                let new_count = match &row_to_decr.data[len - 1] {
-                                    DataType::Int(count) => count - 1,
-                                    _ => 0,
-                                };
-                                row_to_decr.data[len - 1] = DataType::Int(new_count);
-
-                                //sends insertion change downstream if not decremented to 0
-                                if new_count > 0 {
-                                    let mut change_rows_ins = Vec::new();
-                                    change_rows_ins.push(row_to_decr.clone());
-
-                                    let insert_new = Change::new(ChangeType::Insertion, change_rows_ins);
-                                    next_change_vec.push(insert_new);
-                                }
-                            },
-                            None => {}
-                        }
+                if change.typing == ChangeType::Deletion && !self.totals.contains_key(&temp_key) {
+                    continue;
+                }
+
+                let value = match self.function {
+                    FuncType::SUM(col) | FuncType::AVG(col) => row.data[col].widened(),
+                    FuncType::COUNT => DataType::None,
+                };
+
+                let totals = self.totals.entry(temp_key.clone())
+                    .or_insert(Totals { rows: 0, values: 0, sum: DataType::None, poisoned: false });
+                let step = if change.typing == ChangeType::Insertion { 1 } else { -1 };
+
+                totals.rows += step;
+
+                if value != DataType::None {
+                    totals.values += step as i64;
+                    //overflow or a mismatched type leaves the sum NULL, later rows can't bring it back
+                    let sum = match change.typing {
+                        ChangeType::Insertion => totals.sum.checked_add(&value),
+                        ChangeType::Deletion => totals.sum.checked_sub(&value),
+                    };
+
+                    match sum {
+                        Some(sum) if !totals.poisoned => totals.sum = sum,
+                        _ => {
+                            totals.poisoned = true;
+                            totals.sum = DataType::None;
+                        },
                     }
                 }
+
+                let aggregate = match self.function {
+                    FuncType::COUNT => DataType::Int(totals.rows),
+                    FuncType::SUM(_) | FuncType::AVG(_) if totals.poisoned => DataType::None,
+                    FuncType::SUM(_) if totals.values == 0 => DataType::None,
+                    FuncType::SUM(_) => totals.sum.clone(),
+                    FuncType::AVG(_) => totals.sum.checked_avg(totals.values).unwrap_or(DataType::None),
+                };
+                let remaining = totals.rows;
+
+                //sends deletion change downstream for the old aggregate
+                if let Some(old_row) = self.state.remove(&temp_key) {
+                    let delete_old = Change::new(ChangeType::Deletion, vec![old_row]);
+                    next_change_vec.push(delete_old);
+                }
+
+                //sends insertion change downstream if the group still has rows
+                if remaining > 0 {
+                    let mut new_row_vec = temp_key.clone();
+                    new_row_vec.push(aggregate);
+
+                    let new_row = Row::new(new_row_vec);
+                    self.state.insert(temp_key, new_row.clone());
+
+                    let insert_new = Change::new(ChangeType::Insertion, vec![new_row]);
+                    next_change_vec.push(insert_new);
+                } else {
+                    self.totals.remove(&temp_key);
+                }
             }
         }
 
         next_change_vec
     }
 }
+
+impl Aggregation {
+    /// Group by columns followed by the aggregate
+    pub fn output_schema(&self, parent: &Schema) -> Result<Schema, String> {
+        let mut schema = Schema::new(Vec::new(), Vec::new());
+
+        for index in &self.group_by_col {
+            let (name, schema_type) = parent.column(*index).map_err(|err| format!("aggregation: {}", err))?;
+            schema.push(name, schema_type);
+        }
+
+        match self.function {
+            FuncType::COUNT => schema.push("Count".to_owned(), SchemaType::Int),
+            FuncType::SUM(col) => {
+                let (name, schema_type) = parent.column(col).map_err(|err| format!("aggregation: {}", err))?;
+                let sum_type = match schema_type {
+                    SchemaType::Int | SchemaType::BigInt => SchemaType::BigInt,
+                    SchemaType::Float => SchemaType::Float,
+                    SchemaType::Decimal(scale) => SchemaType::Decimal(scale),
+                    other => return Err(format!("aggregation: can't SUM column {} of type {:?}", name, other)),
+                };
+
+                schema.push(format!("Sum{}", name), sum_type);
+            },
+            FuncType::AVG(col) => {
+                let (name, schema_type) = parent.column(col).map_err(|err| format!("aggregation: {}", err))?;
+                let avg_type = match schema_type {
+                    SchemaType::Int | SchemaType::BigInt | SchemaType::Float => SchemaType::Float,
+                    SchemaType::Decimal(scale) => SchemaType::Decimal(scale),
+                    other => return Err(format!("aggregation: can't AVG column {} of type {:?}", name, other)),
+                };
+
+                schema.push(format!("Avg{}", name), avg_type);
+            },
+        }
+
+        Ok(schema)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aggregation(function: FuncType) -> Aggregation {
+        Aggregation { group_by_col: vec![0], function, state: HashMap::new(), totals: HashMap::new() }
+    }
+
+    fn row(group: &str, value: DataType) -> Row {
+        Row::new(vec![DataType::Text(group.to_owned()), value])
+    }
+
+    fn decimal(text: &str) -> DataType {
+        DataType::Decimal(text.parse().unwrap())
+    }
+
+    //the aggregate in the last row inserted downstream
+    fn aggregate(changes: &[Change]) -> Option<DataType> {
+        changes.iter().rev()
+            .find(|change| change.typing == ChangeType::Insertion)
+            .map(|change| change.batch[0].data[1].clone())
+    }
+
+    fn insert(agg: &mut Aggregation, value: DataType) -> Option<DataType> {
+        aggregate(&agg.apply(vec![Change::new(ChangeType::Insertion, vec![row("g", value)])]))
+    }
+
+    fn delete(agg: &mut Aggregation, value: DataType) -> Vec<Change> {
+        agg.apply(vec![Change::new(ChangeType::Deletion, vec![row("g", value)])])
+    }
+
+    #[test]
+    fn sum_widens_int_columns() {
+        let mut agg = aggregation(FuncType::SUM(1));
+
+        insert(&mut agg, DataType::Int(i32::MAX));
+        assert_eq!(insert(&mut agg, DataType::Int(i32::MAX)), Some(DataType::BigInt(2 * i32::MAX as i64)));
+    }
+
+    #[test]
+    fn sum_of_decimals_is_exact_across_scales() {
+        let mut agg = aggregation(FuncType::SUM(1));
+
+        insert(&mut agg, decimal("0.10"));
+        insert(&mut agg, decimal("0.2"));
+        assert_eq!(insert(&mut agg, decimal("-0.005")), Some(decimal("0.295")));
+    }
+
+    #[test]
+    fn insert_then_delete_restores_the_sum() {
+        let mut agg = aggregation(FuncType::SUM(1));
+
+        insert(&mut agg, decimal("19.99"));
+        insert(&mut agg, decimal("0.01"));
+        delete(&mut agg, decimal("0.01"));
+        assert_eq!(insert(&mut agg, decimal("5.00")), Some(decimal("24.99")));
+
+        delete(&mut agg, decimal("5.00"));
+        let last = delete(&mut agg, decimal("19.99"));
+        assert_eq!(aggregate(&last), None);
+        assert!(agg.totals.is_empty());
+    }
+
+    #[test]
+    fn null_values_are_skipped_by_sum_and_avg() {
+        let mut sum = aggregation(FuncType::SUM(1));
+        let mut avg = aggregation(FuncType::AVG(1));
+
+        assert_eq!(insert(&mut sum, DataType::None), Some(DataType::None));
+        assert_eq!(insert(&mut sum, DataType::Int(4)), Some(DataType::BigInt(4)));
+
+        insert(&mut avg, DataType::Int(4));
+        insert(&mut avg, DataType::None);
+        assert_eq!(insert(&mut avg, DataType::Int(5)), Some(DataType::Float(4.5.into())));
+    }
+
+    #[test]
+    fn avg_of_decimals_keeps_the_scale_and_rounds() {
+        let mut agg = aggregation(FuncType::AVG(1));
+
+        insert(&mut agg, decimal("1.00"));
+        insert(&mut agg, decimal("1.00"));
+        assert_eq!(insert(&mut agg, decimal("1.01")), Some(decimal("1.00")));
+        assert_eq!(insert(&mut agg, decimal("-7.05")), Some(decimal("-1.01")));
+    }
+
+    #[test]
+    fn overflow_keeps_sum_and_avg_null() {
+        let mut sum = aggregation(FuncType::SUM(1));
+        let mut avg = aggregation(FuncType::AVG(1));
+
+        for agg in [&mut sum, &mut avg] {
+            insert(agg, DataType::BigInt(i64::MAX));
+            assert_eq!(insert(agg, DataType::BigInt(1)), Some(DataType::None));
+            assert_eq!(insert(agg, DataType::BigInt(1)), Some(DataType::None));
+            delete(agg, DataType::BigInt(i64::MAX));
+            assert_eq!(insert(agg, DataType::BigInt(1)), Some(DataType::None));
+        }
+    }
+
+    #[test]
+    fn mismatched_types_keep_the_sum_null() {
+        let mut agg = aggregation(FuncType::SUM(1));
+
+        insert(&mut agg, DataType::Int(1));
+        assert_eq!(insert(&mut agg, DataType::Float(1.5.into())), Some(DataType::None));
+        assert_eq!(insert(&mut agg, DataType::Int(2)), Some(DataType::None));
+    }
+
+    #[test]
+    fn poisoned_group_recovers_once_empty() {
+        let mut agg = aggregation(FuncType::SUM(1));
+
+        insert(&mut agg, DataType::Int(1));
+        insert(&mut agg, DataType::Float(1.5.into()));
+        delete(&mut agg, DataType::Int(1));
+        delete(&mut agg, DataType::Float(1.5.into()));
+        assert_eq!(insert(&mut agg, DataType::Int(2)), Some(DataType::BigInt(2)));
+    }
+
+    #[test]
+    fn count_counts_null_rows() {
+        let mut agg = aggregation(FuncType::COUNT);
+
+        insert(&mut agg, DataType::None);
+        assert_eq!(insert(&mut agg, DataType::Int(1)), Some(DataType::Int(2)));
+    }
+}
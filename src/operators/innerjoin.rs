@@ -2,6 +2,7 @@ use crate::units::row::Row;
 use crate::units::change::Change;
 use crate::types::changetype::ChangeType;
 use crate::types::datatype::DataType;
+use crate::units::schema::Schema;
 use crate::viewsandgraphs::dfg::DataFlowGraph;
 use petgraph::graph::NodeIndex;
 use crate::operators::Operator;
@@ -46,6 +47,44 @@ impl Operator for InnerJoin {
 }
 
 impl InnerJoin {
+    /// Left loses its join column, right is appended whole
+    pub fn output_schema(&self, parents: &[(usize, &Schema)]) -> Result<Schema, String> {
+        let left = self.parent_schema(parents, 0)?;
+        let right = self.parent_schema(parents, 1)?;
+
+        let (left_name, left_type) = left.column(self.join_cols[0]).map_err(|err| format!("join left: {}", err))?;
+        let (right_name, right_type) = right.column(self.join_cols[1]).map_err(|err| format!("join right: {}", err))?;
+
+        if left_type != right_type {
+            return Err(format!("join: column {} of type {:?} can't join column {} of type {:?}", 
+                left_name, left_type, right_name, right_type));
+        }
+
+        let mut schema = left.clone();
+        schema.column_names.remove(self.join_cols[0]);
+        schema.types.remove(self.join_cols[0]);
+        schema.column_names.extend(right.column_names.clone());
+        schema.types.extend(right.types.clone());
+
+        Ok(schema)
+    }
+
+    fn parent_schema<'a>(&self, parents: &[(usize, &'a Schema)], side: usize) -> Result<&'a Schema, String> {
+        let parent_id = match self.parent_ids.get(side) {
+            Some(id) => *id,
+            None => return Err("join: expects two parent_ids".to_owned()),
+        };
+
+        if self.join_cols.len() != 2 {
+            return Err("join: expects two join_cols".to_owned());
+        }
+
+        match parents.iter().find(|(index, _)| *index == parent_id) {
+            Some((_, schema)) => Ok(schema),
+            None => Err(format!("join: parent {} is not connected", parent_id)),
+        }
+    }
+
     fn apply_join(&mut self, prev_change_vec: Vec<Change>, p_id: NodeIndex) -> Vec<Change> {
         //pid check for left vs right
         //in comparison to aggregate, don't think I need 'joined' state, because have to recheck and 
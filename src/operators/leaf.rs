@@ -1,17 +1,22 @@
 use crate::units::change::Change;
 use crate::units::row::Row;
+use crate::units::schema::Schema;
+use crate::units::rangequery::RangeQuery;
 use crate::units::serverchange::ServerChange;
+use crate::units::rowfilter::RowFilter;
+use crate::units::subscription::Resume;
 use crate::viewsandgraphs::dfg::DataFlowGraph;
 use crate::viewsandgraphs::view::View;
+use crate::viewsandgraphs::index::Index;
 use crate::types::changetype::ChangeType;
 use crate::types::datatype::DataType;
+use crate::types::rowpolicy::RowPolicy;
 use petgraph::graph::NodeIndex;
 use crate::operators::Operator;
-use tungstenite::protocol::WebSocket;
-use std::collections::HashMap;
-use tungstenite::Message;
-use tungstenite::stream::Stream;
-use std::net::TcpStream;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::subscribers::{SendError, Subscriber};
 use std::io::Read;
 use std::cell::{RefCell};
 
@@ -19,18 +24,51 @@ fn return_hash_v() -> HashMap<DataType, Row> {
     HashMap::new()
 }
 
-fn return_vec_v() -> Vec<WebSocket<TcpStream>> {
-    Vec::new()
+//Subscription, a Subscriber listening to a Leaf, changes are handed to subscriber
+//keys limits it to rows with those values in key_index, None gets every row
+//filter is the Leaf's RowPolicies for the subscribing user, None if the Leaf has none
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub(crate) id: u64,
+    pub(crate) subscriber: Arc<dyn Subscriber>,
+    pub(crate) keys: Option<HashSet<DataType>>,
+    pub(crate) filter: Option<RowFilter>,
 }
 
+//how handing a change to a subscriber went, Coalesced means a snapshot took the place of what it held
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Delivery {
+    Sent,
+    Coalesced,
+    Closed,
+}
+
+//index name for lookups on the table itself, keyed on key_index
+pub const PRIMARY_INDEX: &str = "primary";
+
+//how many past changes a Leaf keeps for subscribers catching up after a reconnect
+pub const HISTORY_LEN: usize = 1024;
+
 //Leaf Operator
 //stored view is what is "accessed" by JS
-#[derive(Debug)]
+//table: keyed on key_index, holds one row per key unless the Leaf is multi row
+//indexes: secondary indexes by name, kept in step with table
+//subscriptions: connections the Leaf pushes its changes to
+//seq: number of the last change applied, history: the last history_len changes with their seq
+//epoch: names this run of the Leaf, seq starts over with a new epoch whenever the server does
+//policies: limit the rows each user reads or hears about, applied per subscription
+#[derive(Debug, Clone)]
 pub struct Leaf {
-    pub(crate) table: HashMap<DataType, Row>,
-    pub(crate) sockets: Vec<WebSocket<TcpStream>>,
+    pub(crate) table: Index,
+    pub(crate) subscriptions: Vec<Subscription>,
     pub(crate) root_pair_id: String,
     key_index: usize,
+    pub(crate) indexes: HashMap<String, Index>,
+    pub(crate) epoch: String,
+    pub(crate) seq: u64,
+    pub(crate) history: VecDeque<(u64, Vec<Change>)>,
+    pub(crate) history_len: usize,
+    pub(crate) policies: Vec<RowPolicy>,
 }
 
 //Operator Trait for Leaf
@@ -41,12 +79,23 @@ impl Operator for Leaf {
             for row in &change.batch {
                 match change.typing {
                     ChangeType::Insertion => {
-                        let key = row.data[self.key_index].clone();
-                        self.table.insert(key, row.clone());
+                        //in a single row Leaf a row with the same key is overwritten, so leaves the indexes too
+                        for old_row in self.table.insert(row) {
+                            for index in self.indexes.values_mut() {
+                                index.remove(&old_row);
+                            }
+                        }
+
+                        for index in self.indexes.values_mut() {
+                            index.insert(row);
+                        }
                     },
                     ChangeType::Deletion => {
-                        let key = row.data[self.key_index].clone();
-                        self.table.remove(&key);
+                        for old_row in self.table.remove(row) {
+                            for index in self.indexes.values_mut() {
+                                index.remove(&old_row);
+                            }
+                        }
                     },
                 }
             }
@@ -59,57 +108,480 @@ impl Operator for Leaf {
     fn process_change(&mut self, change: Vec<Change>, _dfg: &DataFlowGraph, _parent_index: NodeIndex, _self_index: NodeIndex) { 
         self.apply(change.clone());  
 
-        let server_change = ServerChange::new(self.root_pair_id.clone(), change);
+        self.seq += 1;
+        self.history.push_back((self.seq, change.clone()));
 
-        let mut remove_vec = Vec::new();
+        if self.history.len() > self.history_len {
+            self.history.pop_front();
+        }
 
-        for n in 0..self.sockets.len() {
-            let msg = Message::text(serde_json::to_string(&server_change.clone()).unwrap());
-            let ws = self.get_ws(n);
+        let mut closed = Vec::new();
 
-            match ws.write_message(msg) {
-                Ok(message) => (),
-                Err(error) => {
-                    remove_vec.push(n);
-                },
+        for subscription in &self.subscriptions {
+            //each subscription only hears about the keys it asked for and the rows its user can see
+            let sub_change = match self.changes_for(subscription, &change) {
+                Some(sub_change) => sub_change,
+                None => continue,
             };
-        }
 
-        if (!remove_vec.is_empty()) {
-            for index in remove_vec {
-                let ws = self.get_ws(index);
-                ws.close(None);
-                self.sockets.remove(index);
+            //changes filtered out for a subscription leave gaps in the seqs it sees, that's fine for resuming
+            let server_change = ServerChange::new(self.root_pair_id.clone(), sub_change)
+                .for_subscription(subscription.id)
+                .with_seq(&self.epoch, self.seq);
+
+            if self.deliver(subscription, &server_change) == Delivery::Closed {
+                closed.push(subscription.id);
             }
         }
+
+        for id in closed {
+            self.unsubscribe(id);
+        }
     }
 }
 
 impl Leaf {
-    pub fn new(root_pair_id: String, key_index: usize) -> Leaf {
-        let table = HashMap::new();
-        let sockets = Vec::new();
+    /// multi_row keeps every row for a key, otherwise a new row replaces the old one
+    pub fn new(root_pair_id: String, key_index: usize, multi_row: bool) -> Leaf {
+        let table = Index::new(key_index, !multi_row, false);
+        let subscriptions = Vec::new();
+        let indexes = HashMap::new();
+        let history = VecDeque::new();
+
+        let policies = Vec::new();
+
+        let epoch = Uuid::new_v4().to_string();
+
+        Leaf { table, subscriptions, root_pair_id, key_index, indexes, epoch, seq: 0, history, history_len: HISTORY_LEN, policies }
+    }
+
+    /// Adds a RowPolicy, every policy on the Leaf has to hold for a user to see a row
+    pub fn add_policy(&mut self, policy: RowPolicy) {
+        self.policies.push(policy);
+    }
+
+    /// Adds a secondary index on col, filled from rows already in the table
+    /// ordered indexes can also be read by key range
+    pub fn add_index(&mut self, name: String, col: usize, ordered: bool) {
+        let mut index = Index::new(col, false, ordered);
+
+        for row in self.table.rows() {
+            index.insert(row);
+        }
+
+        self.indexes.insert(name, index);
+    }
+
+    /// Returns the column an index is keyed on
+    pub fn index_col(&self, index_name: &str) -> Option<usize> {
+        match self.indexes.get(index_name) {
+            Some(index) => Some(index.col),
+            None if index_name == PRIMARY_INDEX => Some(self.key_index),
+            None => None,
+        }
+    }
+
+    /// Returns every row matching key in the named index, PRIMARY_INDEX looks in the table
+    pub fn lookup(&self, index_name: &str, key: &DataType) -> Result<Vec<Row>, String> {
+        if index_name == PRIMARY_INDEX {
+            return Ok(self.table.get(key));
+        }
+
+        match self.indexes.get(index_name) {
+            Some(index) => Ok(index.get(key)),
+            None => Err(format!("leaf {} has no index {}", self.root_pair_id, index_name)),
+        }
+    }
+
+    /// Returns rows from the named ordered index, in key order, leaving out rows filter hides
+    pub fn range(&self, index_name: &str, query: &RangeQuery, filter: Option<&RowFilter>) -> Result<Vec<Row>, String> {
+        let index = match self.indexes.get(index_name) {
+            Some(index) => index,
+            None if index_name == PRIMARY_INDEX => &self.table,
+            None => return Err(format!("leaf {} has no index {}", self.root_pair_id, index_name)),
+        };
+
+        index.range(query, filter).map_err(|err| format!("leaf {} index {}: {}", self.root_pair_id, index_name, err))
+    }
+
+    /// Same as parent, key_index and index columns have to be in bounds
+    pub fn output_schema(&self, parent: &Schema) -> Result<Schema, String> {
+        parent.column(self.key_index).map_err(|err| format!("leaf {}: {}", self.root_pair_id, err))?;
+
+        for (name, index) in &self.indexes {
+            parent.column(index.col).map_err(|err| format!("leaf {} index {}: {}", self.root_pair_id, name, err))?;
+        }
 
-        Leaf { table, sockets, root_pair_id, key_index }
+        for policy in &self.policies {
+            match policy {
+                RowPolicy::ColumnEqualsUser(col) => {
+                    parent.column(*col).map_err(|err| format!("leaf {} policy: {}", self.root_pair_id, err))?;
+                },
+            }
+        }
+
+        Ok(parent.clone())
+    }
+
+    /// Keeps only the rows whose key is in keys, Changes left empty stay in place
+    pub fn filter_changes(&self, change_vec: &[Change], keys: &HashSet<DataType>) -> Vec<Change> {
+        change_vec.iter()
+            .map(|change| {
+                let batch = change.batch.iter()
+                    .filter(|row| keys.contains(&row.data[self.key_index]))
+                    .cloned()
+                    .collect();
+
+                Change::new(change.typing.clone(), batch)
+            })
+            .collect()
+    }
+
+    /// Returns the part of a change a subscription hears about, None if that's nothing
+    fn changes_for(&self, subscription: &Subscription, change_vec: &[Change]) -> Option<Vec<Change>> {
+        let mut sub_change = match &subscription.keys {
+            Some(keys) => self.filter_changes(change_vec, keys),
+            None => change_vec.to_vec(),
+        };
+
+        if let Some(filter) = &subscription.filter {
+            sub_change = filter.filter_changes(&sub_change);
+        }
+
+        if sub_change.iter().all(|c| c.batch.is_empty()) {
+            return None;
+        }
+
+        Some(sub_change)
+    }
+
+    /// Hands the current rows to a new subscriber and subscribes it to further changes,
+    /// keys limits both to rows with those keys, None is the whole table
+    /// filter limits both to the rows the subscribing user can see
+    /// since is where a reconnecting client left off, if it's from this epoch and the history still goes back
+    /// that far it only gets the changes it missed instead of the rows
+    pub fn subscribe(&mut self, id: u64, subscriber: Arc<dyn Subscriber>, keys: Option<HashSet<DataType>>, filter: Option<RowFilter>, since: Option<Resume>) {
+        let subscription = Subscription { id, subscriber, keys, filter };
+        let missed = since
+            .filter(|resume| resume.epoch == self.epoch)
+            .and_then(|resume| self.missed_changes(resume.seq));
+
+        let initial = match missed {
+            Some(missed) => missed.into_iter()
+                .filter_map(|(seq, change)| {
+                    let sub_change = self.changes_for(&subscription, &change)?;
+
+                    Some(ServerChange::new(self.root_pair_id.clone(), sub_change).for_subscription(id).with_seq(&self.epoch, seq))
+                })
+                .collect(),
+            None => vec![self.snapshot(&subscription.keys, &subscription.filter).for_subscription(id)],
+        };
+
+        //a subscriber that's already gone never gets added, one that filled up already has a snapshot
+        //that's current, so the rest of what it missed is left out
+        for server_change in initial {
+            match self.deliver(&subscription, &server_change) {
+                Delivery::Sent => {},
+                Delivery::Coalesced => break,
+                Delivery::Closed => return,
+            }
+        }
+
+        self.subscriptions.push(subscription);
+    }
+
+    /// Hands a change to a subscriber, one that's full gets a snapshot in place of what it still holds
+    fn deliver(&self, subscription: &Subscription, server_change: &ServerChange) -> Delivery {
+        let id = subscription.id;
+
+        match subscription.subscriber.send(server_change) {
+            Ok(()) => Delivery::Sent,
+            Err(SendError::Full) => {
+                let snapshot = self.snapshot(&subscription.keys, &subscription.filter).for_subscription(id);
+
+                match subscription.subscriber.replace_pending(id, &snapshot) {
+                    Ok(()) => Delivery::Coalesced,
+                    Err(_) => Delivery::Closed,
+                }
+            },
+            Err(SendError::Closed) => Delivery::Closed,
+        }
     }
 
-    pub fn get_ws(&mut self, index: usize) -> &mut WebSocket<TcpStream> {
-        self.sockets.get_mut(index).unwrap()
+    /// Returns every change after since with its seq, None if the history doesn't reach back that far
+    pub fn missed_changes(&self, since: u64) -> Option<Vec<(u64, Vec<Change>)>> {
+        if since > self.seq {
+            return None;
+        }
+
+        //the oldest change kept has to be the one right after since
+        let oldest = self.history.front().map_or(self.seq + 1, |(seq, _)| *seq);
+
+        if since + 1 < oldest {
+            return None;
+        }
+
+        Some(self.history.iter().filter(|(seq, _)| *seq > since).cloned().collect())
     }
 
-    pub fn initial_connect(&mut self, mut ws: WebSocket<TcpStream>) {
-        //handle ended connection, remove websocket from vec
+    /// Returns the current rows as one Insertion, stamped with the seq they are current as of
+    /// filter leaves out the rows a user can't see
+    pub fn snapshot(&self, keys: &Option<HashSet<DataType>>, filter: &Option<RowFilter>) -> ServerChange {
         let mut batch = Vec::new();
 
-        for (key, val) in &self.table {
-            batch.push(val.clone());
+        match keys {
+            Some(keys) => {
+                for key in keys {
+                    batch.extend(self.table.get(key));
+                }
+            },
+            None => {
+                for row in self.table.rows() {
+                    batch.push(row.clone());
+                }
+            },
+        }
+
+        if let Some(filter) = filter {
+            batch = filter.filter_rows(batch);
         }
 
         let initial_change = Change::new(ChangeType::Insertion, batch);
-        let init_sc = ServerChange::new(self.root_pair_id.clone(), vec![initial_change]);
 
-        let msg = Message::text(serde_json::to_string(&init_sc).unwrap());
-        ws.write_message(msg).unwrap();
-        self.sockets.push(ws);
+        ServerChange::new(self.root_pair_id.clone(), vec![initial_change]).with_seq(&self.epoch, self.seq).as_snapshot()
+    }
+
+    /// Removes a subscription, returns whether it was here
+    pub fn unsubscribe(&mut self, id: u64) -> bool {
+        let before = self.subscriptions.len();
+        self.subscriptions.retain(|subscription| subscription.id != id);
+
+        self.subscriptions.len() != before
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::Receiver;
+    use crate::subscribers::channel::{ChannelSubscriber, QueueConfig};
+    use crate::subscribers::inprocess::InProcessSubscriber;
+    use crate::types::slowconsumerpolicy::SlowConsumerPolicy;
+
+    fn row(key: i32, value: &str) -> Row {
+        Row::new(vec![DataType::Int(key), DataType::Text(value.to_owned())])
+    }
+
+    fn insert(leaf: &mut Leaf, rows: Vec<Row>) {
+        let dfg = DataFlowGraph::new();
+
+        leaf.process_change(vec![Change::new(ChangeType::Insertion, rows)], &dfg, NodeIndex::new(0), NodeIndex::new(0));
+    }
+
+    fn delete(leaf: &mut Leaf, rows: Vec<Row>) {
+        let dfg = DataFlowGraph::new();
+
+        leaf.process_change(vec![Change::new(ChangeType::Deletion, rows)], &dfg, NodeIndex::new(0), NodeIndex::new(0));
+    }
+
+    fn subscribe(leaf: &mut Leaf, id: u64, keys: Option<Vec<i32>>, since: Option<Resume>) -> Receiver<ServerChange> {
+        let (subscriber, receiver) = InProcessSubscriber::new();
+        let keys = keys.map(|keys| keys.into_iter().map(DataType::Int).collect());
+
+        leaf.subscribe(id, Arc::new(subscriber), keys, None, since);
+
+        receiver
+    }
+
+    fn rows(server_change: &ServerChange) -> Vec<Row> {
+        server_change.changes.iter().flat_map(|change| change.batch.clone()).collect()
+    }
+
+    #[test]
+    fn subscriber_only_hears_its_keys() {
+        let mut leaf = Leaf::new("Stories".to_owned(), 0, false);
+        let receiver = subscribe(&mut leaf, 1, Some(vec![1]), None);
+        assert!(rows(&receiver.try_recv().unwrap()).is_empty());
+
+        insert(&mut leaf, vec![row(1, "a"), row(2, "b")]);
+        assert_eq!(rows(&receiver.try_recv().unwrap()), vec![row(1, "a")]);
+
+        insert(&mut leaf, vec![row(2, "c")]);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn changes_carry_their_subscription_id() {
+        let mut leaf = Leaf::new("Stories".to_owned(), 0, false);
+        let (subscriber, receiver) = InProcessSubscriber::new();
+        let subscriber: Arc<dyn Subscriber> = Arc::new(subscriber);
+        leaf.subscribe(7, subscriber.clone(), Some([DataType::Int(1)].iter().cloned().collect()), None, None);
+        leaf.subscribe(8, subscriber, Some([DataType::Int(2)].iter().cloned().collect()), None, None);
+        receiver.try_iter().for_each(drop);
+
+        insert(&mut leaf, vec![row(2, "b")]);
+        insert(&mut leaf, vec![row(1, "a")]);
+
+        let ids: Vec<Option<u64>> = receiver.try_iter().map(|change| change.subscription_id).collect();
+        assert_eq!(ids, vec![Some(8), Some(7)]);
+    }
+
+    #[test]
+    fn changes_and_snapshots_are_stamped_with_epoch_and_seq() {
+        let mut leaf = Leaf::new("Stories".to_owned(), 0, false);
+        let early = subscribe(&mut leaf, 1, None, None);
+        insert(&mut leaf, vec![row(1, "a")]);
+        insert(&mut leaf, vec![row(2, "b")]);
+
+        let seqs: Vec<(Option<u64>, bool)> = early.try_iter().map(|change| (change.seq, change.snapshot)).collect();
+        assert_eq!(seqs, vec![(Some(0), true), (Some(1), false), (Some(2), false)]);
+
+        let late = subscribe(&mut leaf, 2, None, None);
+        let snapshot = late.try_recv().unwrap();
+        assert!(snapshot.snapshot);
+        assert_eq!(snapshot.seq, Some(2));
+        assert_eq!(snapshot.epoch.as_deref(), Some(leaf.epoch.as_str()));
+        assert_eq!(rows(&snapshot).len(), 2);
+    }
+
+    #[test]
+    fn resume_within_the_epoch_gets_only_missed_changes() {
+        let mut leaf = Leaf::new("Stories".to_owned(), 0, false);
+        for key in 1..=3 {
+            insert(&mut leaf, vec![row(key, "a")]);
+        }
+
+        let since = Resume { epoch: leaf.epoch.clone(), seq: 1 };
+        let missed: Vec<ServerChange> = subscribe(&mut leaf, 1, None, Some(since)).try_iter().collect();
+
+        assert_eq!(missed.iter().map(|change| change.seq).collect::<Vec<_>>(), vec![Some(2), Some(3)]);
+        assert!(missed.iter().all(|change| !change.snapshot));
+        assert_eq!(rows(&missed[0]), vec![row(2, "a")]);
+    }
+
+    #[test]
+    fn resume_from_another_epoch_gets_a_snapshot() {
+        let mut leaf = Leaf::new("Stories".to_owned(), 0, false);
+        insert(&mut leaf, vec![row(1, "a")]);
+        insert(&mut leaf, vec![row(2, "b")]);
+
+        //a seq the restarted Leaf has also reached, but it counted different changes
+        let since = Resume { epoch: "before the restart".to_owned(), seq: 1 };
+        let received: Vec<ServerChange> = subscribe(&mut leaf, 1, None, Some(since)).try_iter().collect();
+
+        assert_eq!(received.len(), 1);
+        assert!(received[0].snapshot);
+        assert_eq!(rows(&received[0]).len(), 2);
+    }
+
+    #[test]
+    fn resume_past_the_history_gets_a_snapshot() {
+        let mut leaf = Leaf::new("Stories".to_owned(), 0, false);
+        leaf.history_len = 2;
+        for key in 1..=4 {
+            insert(&mut leaf, vec![row(key, "a")]);
+        }
+
+        let since = Resume { epoch: leaf.epoch.clone(), seq: 1 };
+        let received: Vec<ServerChange> = subscribe(&mut leaf, 1, None, Some(since)).try_iter().collect();
+
+        assert_eq!(received.len(), 1);
+        assert!(received[0].snapshot);
+        assert_eq!(received[0].seq, Some(4));
+    }
+
+    #[test]
+    fn closed_subscriber_is_dropped() {
+        let mut leaf = Leaf::new("Stories".to_owned(), 0, false);
+        drop(subscribe(&mut leaf, 1, None, None));
+        assert_eq!(leaf.subscriptions.len(), 1);
+
+        insert(&mut leaf, vec![row(1, "a")]);
+        assert!(leaf.subscriptions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn full_queue_is_coalesced_into_a_snapshot() {
+        let mut leaf = Leaf::new("Stories".to_owned(), 0, false);
+        let queue = ChannelSubscriber::new(QueueConfig { capacity: 2, policy: SlowConsumerPolicy::Coalesce });
+        leaf.subscribe(1, queue.clone(), None, None, None);
+
+        for key in 1..=3 {
+            insert(&mut leaf, vec![row(key, "a")]);
+        }
+
+        //the initial snapshot and the first change filled the queue, the second change replaced both
+        assert_eq!(leaf.subscriptions.len(), 1);
+        assert_eq!(queue.depth(), 2);
+
+        let snapshot: ServerChange = serde_json::from_str(&queue.recv().await.unwrap()).unwrap();
+        assert!(snapshot.snapshot);
+        assert_eq!(snapshot.seq, Some(2));
+        assert_eq!(rows(&snapshot).len(), 2);
+
+        let change: ServerChange = serde_json::from_str(&queue.recv().await.unwrap()).unwrap();
+        assert!(!change.snapshot);
+        assert_eq!(change.seq, Some(3));
+        assert_eq!(rows(&change), vec![row(3, "a")]);
+    }
+
+    #[tokio::test]
+    async fn resume_that_overflows_the_queue_gets_only_a_snapshot() {
+        let mut leaf = Leaf::new("Stories".to_owned(), 0, false);
+        for key in 1..=5 {
+            insert(&mut leaf, vec![row(key, "a")]);
+        }
+
+        let queue = ChannelSubscriber::new(QueueConfig { capacity: 2, policy: SlowConsumerPolicy::Coalesce });
+        let since = Resume { epoch: leaf.epoch.clone(), seq: 1 };
+        leaf.subscribe(1, queue.clone(), None, None, Some(since));
+
+        //the snapshot already holds the changes that didn't fit, so they aren't sent again after it
+        assert_eq!(leaf.subscriptions.len(), 1);
+        assert_eq!(queue.depth(), 1);
+
+        let snapshot: ServerChange = serde_json::from_str(&queue.recv().await.unwrap()).unwrap();
+        assert!(snapshot.snapshot);
+        assert_eq!(snapshot.seq, Some(5));
+        assert_eq!(rows(&snapshot).len(), 5);
+
+        insert(&mut leaf, vec![row(6, "a")]);
+        let change: ServerChange = serde_json::from_str(&queue.recv().await.unwrap()).unwrap();
+        assert_eq!(change.seq, Some(6));
+        assert_eq!(rows(&change), vec![row(6, "a")]);
+    }
+
+    #[test]
+    fn secondary_index_lookup_finds_rows_by_value() {
+        let mut leaf = Leaf::new("Stories".to_owned(), 0, false);
+        insert(&mut leaf, vec![row(1, "a"), row(2, "b")]);
+        leaf.add_index("by_value".to_owned(), 1, false);
+        insert(&mut leaf, vec![row(3, "a")]);
+
+        let a = DataType::Text("a".to_owned());
+        let mut found = leaf.lookup("by_value", &a).unwrap();
+        found.sort_by_key(|row| row.data[0].clone());
+        assert_eq!(found, vec![row(1, "a"), row(3, "a")]);
+        assert!(leaf.lookup("by_value", &DataType::Text("c".to_owned())).unwrap().is_empty());
+        assert!(leaf.lookup("missing", &a).is_err());
+    }
+
+    #[test]
+    fn secondary_index_follows_deletes_and_overwrites() {
+        let mut leaf = Leaf::new("Stories".to_owned(), 0, false);
+        leaf.add_index("by_value".to_owned(), 1, false);
+        insert(&mut leaf, vec![row(1, "a"), row(2, "a"), row(3, "b")]);
+
+        delete(&mut leaf, vec![row(1, "a")]);
+        assert_eq!(leaf.lookup("by_value", &DataType::Text("a".to_owned())).unwrap(), vec![row(2, "a")]);
+
+        //a new row for key 2 replaces the old one, so it moves to the other value
+        insert(&mut leaf, vec![row(2, "b")]);
+        assert!(leaf.lookup("by_value", &DataType::Text("a".to_owned())).unwrap().is_empty());
+
+        let mut found = leaf.lookup("by_value", &DataType::Text("b".to_owned())).unwrap();
+        found.sort_by_key(|row| row.data[0].clone());
+        assert_eq!(found, vec![row(2, "b"), row(3, "b")]);
     }
 }
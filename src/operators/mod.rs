@@ -9,8 +9,6 @@ pub mod operation;
 use crate::units::change::Change;
 use crate::viewsandgraphs::dfg::DataFlowGraph;
 use petgraph::graph::NodeIndex;
-use tungstenite::protocol::WebSocket;
-use std::net::TcpStream;
 
 //Operator trait
 pub trait Operator {
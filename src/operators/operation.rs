@@ -5,12 +5,11 @@ use super::leaf::Leaf;
 use super::root::Root;
 use super::selection::Selection;
 use crate::units::change::Change;
+use crate::units::schema::Schema;
 use petgraph::graph::NodeIndex;
 use crate::operators::Operator;
 use crate::viewsandgraphs::dfg::DataFlowGraph;
 use std::io::Read;
-use tungstenite::protocol::WebSocket;
-use std::net::TcpStream;
 
 //Operation Enum, used for typing
 //I think this was originally for exposing operators to JS, but now that operator stuff is handled
@@ -48,4 +47,27 @@ impl Operator for Operation {
             Operation::InnerJoinor(op) => op.process_change(change, dfg, parent_index, self_index),
         }
     }
+}
+
+//unary operators have a single parent to take their Schema from
+fn only_parent<'a>(parents: &[(usize, &'a Schema)]) -> Result<&'a Schema, String> {
+    match parents {
+        [(_, parent)] => Ok(parent),
+        _ => Err(format!("expected exactly one parent, found {}", parents.len())),
+    }
+}
+
+//Operation functions
+impl Operation {
+    /// Returns the Schema of the rows this node outputs, given (index, Schema) of each parent
+    pub fn output_schema(&self, parents: &[(usize, &Schema)]) -> Result<Schema, String> {
+        match self {
+            Operation::Selector(op) => op.output_schema(only_parent(parents)?),
+            Operation::Projector(op) => op.output_schema(only_parent(parents)?),
+            Operation::Aggregator(op) => op.output_schema(only_parent(parents)?),
+            Operation::Rootor(op) => op.output_schema(),
+            Operation::Leafor(op) => op.output_schema(only_parent(parents)?),
+            Operation::InnerJoinor(op) => op.output_schema(parents),
+        }
+    }
 }
\ No newline at end of file
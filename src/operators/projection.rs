@@ -1,5 +1,6 @@
 use crate::units::row::Row;
 use crate::units::change::Change;
+use crate::units::schema::Schema;
 use crate::operators::Operator;
 
 //Projection Operator
@@ -32,4 +33,18 @@ impl Operator for Projection {
 
         next_change_vec
     }
+}
+
+impl Projection {
+    /// Selected columns, in the order given
+    pub fn output_schema(&self, parent: &Schema) -> Result<Schema, String> {
+        let mut schema = Schema::new(Vec::new(), Vec::new());
+
+        for index in &self.columns {
+            let (name, schema_type) = parent.column(*index).map_err(|err| format!("projection: {}", err))?;
+            schema.push(name, schema_type);
+        }
+
+        Ok(schema)
+    }
 }
\ No newline at end of file
@@ -4,22 +4,34 @@ use petgraph::graph::NodeIndex;
 use crate::operators::Operator;
 use std::collections::HashMap;
 use crate::types::datatype::DataType;
+use crate::types::schematype::SchemaType;
 use crate::units::row::Row;
+use crate::units::schema::Schema;
 use crate::types::changetype::ChangeType;
 
-fn return_hash_v() -> HashMap<DataType, Row> {
+fn return_hash_v() -> HashMap<DataType, Vec<Row>> {
     HashMap::new()
 }
 
+fn return_false() -> bool {
+    false
+}
+
 //Root Operator
 //root_id assumed unique, used for NodeIndex mapping to find in graph
+//column_names and schema declare the shape every incoming row must have
+//multi_row keeps every row for a key, otherwise a new row replaces the old one
 #[derive(Debug, Clone)]
 #[derive(Serialize, Deserialize)]
 pub struct Root {
     pub(crate) root_id: String,
     key_index: usize, 
+    pub(crate) column_names: Vec<String>,
+    pub(crate) schema: Vec<SchemaType>,
+    #[serde(default = "return_false")]
+    multi_row: bool,
     #[serde(default = "return_hash_v")]
-    pub(crate) table: HashMap<DataType, Row>,
+    pub(crate) table: HashMap<DataType, Vec<Row>>,
     
 }
 
@@ -32,11 +44,28 @@ impl Operator for Root {
                 match change.typing {
                     ChangeType::Insertion => {
                         let key = row.data[self.key_index].clone();
-                        self.table.insert(key, row.clone());
+
+                        if self.multi_row {
+                            self.table.entry(key).or_default().push(row.clone());
+                        } else {
+                            self.table.insert(key, vec![row.clone()]);
+                        }
                     },
                     ChangeType::Deletion => {
                         let key = row.data[self.key_index].clone();
-                        self.table.remove(&key);
+
+                        if !self.multi_row {
+                            self.table.remove(&key);
+                        } else if let Some(rows) = self.table.get_mut(&key) {
+                            //only the exactly matching row goes, others with the same key stay
+                            if let Some(pos) = rows.iter().position(|r| r == row) {
+                                rows.remove(pos);
+                            }
+
+                            if rows.is_empty() {
+                                self.table.remove(&key);
+                            }
+                        }
                     },
                 }
             }
@@ -60,4 +89,137 @@ impl Operator for Root {
             (*child_ref_mut).process_change(change.clone(), dfg, self_index, child_index);
         }
     }
-}
\ No newline at end of file
+}
+
+impl Root {
+    /// Returns the declared Schema, checking that names, types and key_index line up
+    pub fn output_schema(&self) -> Result<Schema, String> {
+        if self.column_names.len() != self.schema.len() {
+            return Err(format!("root {} has {} column names but {} types", 
+                self.root_id, self.column_names.len(), self.schema.len()));
+        }
+
+        if self.key_index >= self.schema.len() {
+            return Err(format!("root {} key_index {} out of bounds", self.root_id, self.key_index));
+        }
+
+        Ok(Schema::new(self.column_names.clone(), self.schema.clone()))
+    }
+
+    /// Checks arity and types of every row in a set of Changes, nothing should be applied on Err
+    pub fn check_changes(&self, change_vec: &[Change]) -> Result<(), String> {
+        for change in change_vec {
+            for row in &change.batch {
+                self.check_row(row)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks a single row against the declared schema
+    pub fn check_row(&self, row: &Row) -> Result<(), String> {
+        if row.data.len() != self.schema.len() {
+            return Err(format!("root {} expects {} columns, got row with {}", 
+                self.root_id, self.schema.len(), row.data.len()));
+        }
+
+        for (index, (datum, schema_type)) in row.data.iter().zip(self.schema.iter()).enumerate() {
+            if !schema_type.matches(datum) {
+                let column = self.column_names.get(index).cloned().unwrap_or_else(|| index.to_string());
+
+                return Err(format!("root {} column {} expects {:?}, got {:?}", 
+                    self.root_id, column, schema_type, datum));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::leaf::PRIMARY_INDEX;
+    use crate::operators::operation::Operation::Rootor;
+    use crate::types::float::Float;
+    use crate::types::operatortype::OperatorType;
+
+    const STORIES: &str = r#"{
+        "root_id": "Stories",
+        "key_index": 0,
+        "column_names": ["StoryID", "Title", "Score"],
+        "schema": ["Int", "Text", "Float"]
+    }"#;
+
+    fn story(id: i32, title: &str) -> Row {
+        Row::new(vec![DataType::Int(id), DataType::Text(title.to_owned()), DataType::Float(Float(1.0))])
+    }
+
+    //Stories with a Leaf on it, at node 1
+    fn graph() -> DataFlowGraph {
+        let mut graph = DataFlowGraph::new();
+        graph.add_node(OperatorType::R, STORIES.to_owned());
+        graph.add_leaf("Stories".to_owned(), 0, false, "/stories".to_owned());
+        graph.add_edge(0, 1);
+        graph.build_schemas().unwrap();
+
+        graph
+    }
+
+    #[test]
+    fn rows_are_checked_against_the_schema() {
+        let root: Root = serde_json::from_str(STORIES).unwrap();
+
+        assert!(root.check_row(&story(1, "a")).is_ok());
+        assert!(root.check_row(&Row::new(vec![DataType::Int(1), DataType::None, DataType::None])).is_ok());
+
+        let err = root.check_row(&Row::new(vec![DataType::Int(1)])).unwrap_err();
+        assert!(err.contains("expects 3 columns"), "{}", err);
+
+        let err = root.check_row(&Row::new(vec![DataType::Int(1), DataType::Int(2), DataType::Float(Float(1.0))])).unwrap_err();
+        assert!(err.contains("column Title expects Text"), "{}", err);
+    }
+
+    #[test]
+    fn bad_batch_is_rejected_whole() {
+        let graph = graph();
+        let bad = Row::new(vec![DataType::Int(2), DataType::Text("b".to_owned()), DataType::Text("high".to_owned())]);
+        let changes = vec![
+            Change::new(ChangeType::Insertion, vec![story(1, "a")]),
+            Change::new(ChangeType::Insertion, vec![story(3, "c"), bad]),
+        ];
+
+        let err = graph.change_to_root("Stories".to_owned(), changes).unwrap_err();
+        assert!(err.contains("column Score"), "{}", err);
+
+        //the good rows before the bad one weren't applied either
+        assert!(graph.lookup(1, PRIMARY_INDEX, &DataType::Int(1), None).unwrap().is_empty());
+        assert!(graph.lookup(1, PRIMARY_INDEX, &DataType::Int(3), None).unwrap().is_empty());
+
+        match &*graph.data.node_weight(NodeIndex::new(0)).unwrap().read().unwrap() {
+            Rootor(root) => assert!(root.table.is_empty()),
+            _ => panic!("node 0 is not a root"),
+        }
+
+        graph.change_to_root("Stories".to_owned(), vec![Change::new(ChangeType::Insertion, vec![story(1, "a")])]).unwrap();
+        assert_eq!(graph.lookup(1, PRIMARY_INDEX, &DataType::Int(1), None).unwrap(), vec![story(1, "a")]);
+    }
+
+    #[test]
+    fn multi_row_delete_leaves_rows_sharing_the_key() {
+        let mut root: Root = serde_json::from_str(&STORIES.replace(r#""key_index": 0,"#, r#""key_index": 0, "multi_row": true,"#)).unwrap();
+        root.apply(vec![Change::new(ChangeType::Insertion, vec![story(1, "a"), story(1, "b"), story(2, "c")])]);
+
+        root.apply(vec![Change::new(ChangeType::Deletion, vec![story(1, "a")])]);
+        assert_eq!(root.table[&DataType::Int(1)], vec![story(1, "b")]);
+        assert_eq!(root.table[&DataType::Int(2)], vec![story(2, "c")]);
+
+        //deleting a row that isn't there leaves the others alone
+        root.apply(vec![Change::new(ChangeType::Deletion, vec![story(1, "z")])]);
+        assert_eq!(root.table[&DataType::Int(1)], vec![story(1, "b")]);
+
+        root.apply(vec![Change::new(ChangeType::Deletion, vec![story(1, "b")])]);
+        assert!(!root.table.contains_key(&DataType::Int(1)));
+    }
+}
@@ -1,13 +1,24 @@
 use crate::units::change::Change;
 use crate::operators::Operator;
 use crate::types::datatype::DataType;
+use crate::types::comparetype::CompareType;
+use crate::units::schema::Schema;
+
+fn return_eq() -> CompareType {
+    CompareType::EQ
+}
 
 //Selection Operator
+//keeps rows whose col_ind compares to condition, comparison defaults to EQ
+//comparisons follow SQL rather than DataType's ==, so EQ against a NULL condition matches no rows,
+//NULL columns included, and numbers match by value across types, an Int(5) column is EQ to Float(5.0)
 #[derive(Debug, Clone)]
 #[derive(Serialize, Deserialize)]
 pub struct Selection {
     col_ind: usize,
     condition: DataType,
+    #[serde(default = "return_eq")]
+    comparison: CompareType,
 }
 
 //Operator Trait for Selection
@@ -19,7 +30,7 @@ impl Operator for Selection {
             let mut next_change = Change {typing: change.typing, batch: Vec::new()};
 
             for row in &(change.batch) {
-                if row.data[self.col_ind] == self.condition {
+                if self.comparison.test(&row.data[self.col_ind], &self.condition) {
                     next_change.batch.push((*row).clone());
                 }
             }
@@ -29,4 +40,18 @@ impl Operator for Selection {
 
         next_change_vec
     }
+}
+
+impl Selection {
+    /// Same as parent, condition has to fit the selected column
+    pub fn output_schema(&self, parent: &Schema) -> Result<Schema, String> {
+        let (name, schema_type) = parent.column(self.col_ind).map_err(|err| format!("selection: {}", err))?;
+
+        if !schema_type.matches(&self.condition) {
+            return Err(format!("selection: condition {:?} does not match column {} of type {:?}", 
+                self.condition, name, schema_type));
+        }
+
+        Ok(parent.clone())
+    }
 }
\ No newline at end of file
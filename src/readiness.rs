@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+//how long benchmark writers wait for the rest by default
+pub const DEFAULT_GATE_TIMEOUT: Duration = Duration::from_secs(60);
+
+//ReadinessGate, holds benchmark writers back until participants of them have connected
+//so a latency run starts measuring with every client in place
+//arrived: how many have come so far, waiters watch it instead of polling
+#[derive(Debug)]
+pub struct ReadinessGate {
+    participants: usize,
+    timeout: Duration,
+    arrived: watch::Sender<usize>,
+}
+
+//ReadinessGate functions
+impl ReadinessGate {
+    //constructor
+    pub fn new(participants: usize, timeout: Duration) -> ReadinessGate {
+        let (arrived, _) = watch::channel(0);
+
+        ReadinessGate { participants, timeout, arrived }
+    }
+
+    /// Counts a writer in and waits for the rest, returns false if timeout ran out first
+    pub async fn arrive(&self) -> bool {
+        self.arrived.send_modify(|arrived| *arrived += 1);
+
+        let mut arrived = self.arrived.subscribe();
+        let all_in = tokio::time::timeout(self.timeout, arrived.wait_for(|arrived| *arrived >= self.participants)).await
+            .map(|result| result.is_ok())
+            .unwrap_or(false);
+
+        all_in
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn writers_are_held_until_every_participant_arrives() {
+        let gate = Arc::new(ReadinessGate::new(2, DEFAULT_GATE_TIMEOUT));
+        let first = tokio::spawn({
+            let gate = Arc::clone(&gate);
+            async move { gate.arrive().await }
+        });
+
+        //alone, the first writer is still waiting
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!first.is_finished());
+
+        assert!(gate.arrive().await);
+        assert!(first.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn late_arrivals_go_straight_through() {
+        let gate = ReadinessGate::new(1, DEFAULT_GATE_TIMEOUT);
+
+        assert!(gate.arrive().await);
+        assert!(gate.arrive().await);
+    }
+
+    #[tokio::test]
+    async fn timeout_lets_a_writer_start_without_the_rest() {
+        let gate = ReadinessGate::new(2, Duration::from_millis(20));
+
+        assert!(!gate.arrive().await);
+    }
+}
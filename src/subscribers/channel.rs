@@ -0,0 +1,212 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex, Weak};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::sync::Notify;
+
+use crate::subscribers::{SendError, Subscriber};
+use crate::types::slowconsumerpolicy::SlowConsumerPolicy;
+use crate::units::serverchange::ServerChange;
+
+//how many messages a connection can have waiting by default
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+//every live queue, for metrics
+static QUEUES: Mutex<Vec<Weak<ChannelSubscriber>>> = Mutex::new(Vec::new());
+
+//times a full queue was handled by SlowConsumerPolicy::Drop or Coalesce, since startup, long-polls included
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static COALESCED: AtomicU64 = AtomicU64::new(0);
+
+/// Counts a full queue closed under SlowConsumerPolicy::Drop
+pub(crate) fn record_dropped() {
+    DROPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a full queue replaced by a snapshot under SlowConsumerPolicy::Coalesce
+pub(crate) fn record_coalesced() {
+    COALESCED.fetch_add(1, Ordering::Relaxed);
+}
+
+//QueueConfig, size and overflow behaviour of each connection's outgoing queue
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueueConfig {
+    pub capacity: usize,
+    pub policy: SlowConsumerPolicy,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig { capacity: DEFAULT_QUEUE_CAPACITY, policy: SlowConsumerPolicy::Coalesce }
+    }
+}
+
+//QueueMetrics, queues: live connection queues, depth: messages waiting in them now
+//max_depth: deepest single queue now, dropped and coalesced: full queues handled by that policy since startup
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueueMetrics {
+    pub queues: usize,
+    pub depth: usize,
+    pub max_depth: usize,
+    pub dropped: u64,
+    pub coalesced: u64,
+}
+
+//messages: JSON waiting to go out, each with the subscription it's for, None for replies
+#[derive(Debug, Default)]
+struct QueueState {
+    messages: VecDeque<(Option<u64>, String)>,
+    closed: bool,
+}
+
+//ChannelSubscriber, a connection's bounded outgoing queue, shared by all its subscriptions
+//Leafs push into it without touching the network, the connection's task pops onto the socket
+#[derive(Debug)]
+pub struct ChannelSubscriber {
+    config: QueueConfig,
+    state: Mutex<QueueState>,
+    //wakes the connection's task when there's something to send
+    ready: Notify,
+    //wakes Leafs blocked on a full queue under SlowConsumerPolicy::Block
+    space: Condvar,
+}
+
+//ChannelSubscriber functions
+impl ChannelSubscriber {
+    //constructor, the queue counts toward metrics until dropped
+    pub fn new(config: QueueConfig) -> Arc<ChannelSubscriber> {
+        let queue = Arc::new(ChannelSubscriber {
+            config,
+            state: Mutex::new(QueueState::default()),
+            ready: Notify::new(),
+            space: Condvar::new(),
+        });
+
+        let mut queues = QUEUES.lock().unwrap();
+        queues.retain(|queue| queue.strong_count() > 0);
+        queues.push(Arc::downgrade(&queue));
+
+        queue
+    }
+
+    /// Queues a reply for the client, replies count toward the capacity like changes do,
+    /// there is nothing to coalesce a reply into so a full queue is closed under Coalesce as under Drop
+    pub fn push_reply(&self, json: String) -> Result<(), SendError> {
+        self.push(None, json, false)
+    }
+
+    //queues a message, waiting for room under SlowConsumerPolicy::Block,
+    //returns Full instead of closing the queue if the message can be coalesced
+    fn push(&self, subscription_id: Option<u64>, json: String, coalesce: bool) -> Result<(), SendError> {
+        let mut state = self.state.lock().unwrap();
+
+        if self.config.policy == SlowConsumerPolicy::Block {
+            state = self.space.wait_while(state, |state| !state.closed && state.messages.len() >= self.config.capacity).unwrap();
+        }
+
+        if state.closed {
+            return Err(SendError::Closed);
+        }
+
+        if state.messages.len() >= self.config.capacity {
+            if coalesce && self.config.policy == SlowConsumerPolicy::Coalesce {
+                return Err(SendError::Full);
+            }
+
+            state.closed = true;
+            record_dropped();
+            drop(state);
+            self.ready.notify_one();
+
+            return Err(SendError::Closed);
+        }
+
+        state.messages.push_back((subscription_id, json));
+        drop(state);
+        self.ready.notify_one();
+
+        Ok(())
+    }
+
+    /// Waits for the next message to send, None once the queue is closed and empty
+    pub async fn recv(&self) -> Option<String> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+
+                if let Some((_, json)) = state.messages.pop_front() {
+                    self.space.notify_all();
+                    return Some(json);
+                }
+
+                if state.closed {
+                    return None;
+                }
+            }
+
+            self.ready.notified().await;
+        }
+    }
+
+    /// Returns whether the queue was closed, by the connection or by SlowConsumerPolicy::Drop
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    /// Stops taking messages, a Leaf still holding the queue drops it on its next change
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_one();
+        self.space.notify_all();
+    }
+
+    /// Returns the number of messages waiting
+    pub fn depth(&self) -> usize {
+        self.state.lock().unwrap().messages.len()
+    }
+}
+
+impl Subscriber for ChannelSubscriber {
+    fn send(&self, change: &ServerChange) -> Result<(), SendError> {
+        self.push(change.subscription_id, serde_json::to_string(change).unwrap(), true)
+    }
+
+    fn replace_pending(&self, subscription_id: u64, snapshot: &ServerChange) -> Result<(), SendError> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.closed {
+            return Err(SendError::Closed);
+        }
+
+        //the snapshot covers everything the subscription hadn't been sent yet
+        state.messages.retain(|(id, _)| *id != Some(subscription_id));
+        state.messages.push_back((Some(subscription_id), serde_json::to_string(snapshot).unwrap()));
+        record_coalesced();
+        drop(state);
+        self.ready.notify_one();
+
+        Ok(())
+    }
+}
+
+/// Returns queue depths and overflow counts over every live connection queue
+pub fn queue_metrics() -> QueueMetrics {
+    let queues: Vec<Arc<ChannelSubscriber>> = QUEUES.lock().unwrap().iter().filter_map(Weak::upgrade).collect();
+    let mut metrics = QueueMetrics {
+        queues: queues.len(),
+        dropped: DROPPED.load(Ordering::Relaxed),
+        coalesced: COALESCED.load(Ordering::Relaxed),
+        ..QueueMetrics::default()
+    };
+
+    for queue in queues {
+        let depth = queue.depth();
+
+        metrics.depth += depth;
+        metrics.max_depth = metrics.max_depth.max(depth);
+    }
+
+    metrics
+}
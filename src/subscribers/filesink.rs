@@ -0,0 +1,37 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+use crate::subscribers::{SendError, Subscriber};
+use crate::units::serverchange::ServerChange;
+
+//FileSink, appends every change to a file as one JSON line, for audit logs and offline replay
+#[derive(Debug)]
+pub struct FileSink {
+    path: String,
+    file: Mutex<File>,
+}
+
+//FileSink functions
+impl FileSink {
+    /// Opens path for appending, creating it if needed
+    pub fn open(path: &str) -> Result<FileSink, String> {
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|err| format!("can't open {}: {}", path, err))?;
+
+        Ok(FileSink { path: path.to_owned(), file: Mutex::new(file) })
+    }
+}
+
+impl Subscriber for FileSink {
+    fn send(&self, change: &ServerChange) -> Result<(), SendError> {
+        let mut line = serde_json::to_string(change).unwrap();
+        line.push('\n');
+
+        //a sink that can't be written to is dropped like a closed connection
+        self.file.lock().unwrap().write_all(line.as_bytes()).map_err(|err| {
+            error!("can't write {}: {}", self.path, err);
+            SendError::Closed
+        })
+    }
+}
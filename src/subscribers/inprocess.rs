@@ -0,0 +1,28 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+use crate::subscribers::{SendError, Subscriber};
+use crate::units::serverchange::ServerChange;
+
+//InProcessSubscriber, hands changes to code in the same process, for embedding the graph
+//and for checking what a Leaf puts out without opening a socket
+#[derive(Debug)]
+pub struct InProcessSubscriber {
+    sender: Mutex<Sender<ServerChange>>,
+}
+
+//InProcessSubscriber functions
+impl InProcessSubscriber {
+    /// Returns the subscriber and the receiving end its changes arrive on
+    pub fn new() -> (InProcessSubscriber, Receiver<ServerChange>) {
+        let (sender, receiver) = channel();
+
+        (InProcessSubscriber { sender: Mutex::new(sender) }, receiver)
+    }
+}
+
+impl Subscriber for InProcessSubscriber {
+    fn send(&self, change: &ServerChange) -> Result<(), SendError> {
+        self.sender.lock().unwrap().send(change.clone()).map_err(|_| SendError::Closed)
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::subscribers::{SendError, Subscriber};
+use crate::subscribers::channel::{record_coalesced, record_dropped, QueueConfig};
+use crate::types::slowconsumerpolicy::SlowConsumerPolicy;
+use crate::units::serverchange::ServerChange;
+
+//how long a long-poll subscription lives without being polled before its Leaf drops it
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+//pending: changes since the last poll, last_poll: when the client last came for them
+#[derive(Debug)]
+struct LongPollState {
+    pending: Vec<ServerChange>,
+    last_poll: Instant,
+    closed: bool,
+}
+
+impl LongPollState {
+    fn abandoned(&self) -> bool {
+        self.closed || self.last_poll.elapsed() > IDLE_TIMEOUT
+    }
+}
+
+//LongPollSubscriber, holds changes for an HTTP client until its next poll
+//pending is bounded by config the same way a websocket connection's queue is
+#[derive(Debug)]
+pub struct LongPollSubscriber {
+    config: QueueConfig,
+    state: Mutex<LongPollState>,
+    //wakes a poll waiting for changes
+    ready: Condvar,
+    //wakes Leafs blocked on a full pending under SlowConsumerPolicy::Block
+    space: Condvar,
+}
+
+//LongPollSubscriber functions
+impl LongPollSubscriber {
+    //constructor
+    pub fn new(config: QueueConfig) -> LongPollSubscriber {
+        let state = LongPollState { pending: Vec::new(), last_poll: Instant::now(), closed: false };
+
+        LongPollSubscriber { config, state: Mutex::new(state), ready: Condvar::new(), space: Condvar::new() }
+    }
+
+    /// Returns the changes held since the last poll, waiting up to timeout for one if there are none
+    pub fn poll(&self, timeout: Duration) -> Vec<ServerChange> {
+        let mut state = self.state.lock().unwrap();
+        state.last_poll = Instant::now();
+
+        let (mut state, _) = self.ready.wait_timeout_while(state, timeout, |state| state.pending.is_empty() && !state.closed).unwrap();
+        state.last_poll = Instant::now();
+        self.space.notify_all();
+
+        std::mem::take(&mut state.pending)
+    }
+
+    /// Returns whether the subscription is closed or went unpolled for longer than IDLE_TIMEOUT
+    pub fn abandoned(&self) -> bool {
+        self.state.lock().unwrap().abandoned()
+    }
+
+    /// Stops taking changes, the Leaf drops the subscription on its next change
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_all();
+        self.space.notify_all();
+    }
+}
+
+impl Default for LongPollSubscriber {
+    fn default() -> Self {
+        Self::new(QueueConfig::default())
+    }
+}
+
+impl Subscriber for LongPollSubscriber {
+    fn send(&self, change: &ServerChange) -> Result<(), SendError> {
+        let mut state = self.state.lock().unwrap();
+
+        //a client that stops polling is given up on after IDLE_TIMEOUT, so a blocked Leaf gets going again
+        if self.config.policy == SlowConsumerPolicy::Block {
+            while !state.abandoned() && state.pending.len() >= self.config.capacity {
+                state = self.space.wait_timeout(state, IDLE_TIMEOUT).unwrap().0;
+            }
+        }
+
+        if state.abandoned() {
+            return Err(SendError::Closed);
+        }
+
+        if state.pending.len() >= self.config.capacity {
+            if self.config.policy == SlowConsumerPolicy::Coalesce {
+                return Err(SendError::Full);
+            }
+
+            state.closed = true;
+            record_dropped();
+            self.ready.notify_all();
+
+            return Err(SendError::Closed);
+        }
+
+        state.pending.push(change.clone());
+        self.ready.notify_all();
+
+        Ok(())
+    }
+
+    //a long-poll subscriber only ever holds one subscription, so the snapshot replaces everything pending
+    fn replace_pending(&self, _subscription_id: u64, snapshot: &ServerChange) -> Result<(), SendError> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.abandoned() {
+            return Err(SendError::Closed);
+        }
+
+        state.pending = vec![snapshot.clone()];
+        record_coalesced();
+        self.ready.notify_all();
+
+        Ok(())
+    }
+}
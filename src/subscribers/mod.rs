@@ -0,0 +1,38 @@
+pub mod channel;
+pub mod filesink;
+pub mod inprocess;
+pub mod longpoll;
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::units::serverchange::ServerChange;
+
+//subscription ids are unique across every kind of subscriber, so a Leaf never mixes two up
+static NEXT_SUBSCRIPTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Returns a fresh subscription id
+pub fn next_subscription_id() -> u64 {
+    NEXT_SUBSCRIPTION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+//SendError, why a Subscriber didn't take a change
+//Closed: it is gone and the Leaf drops it, Full: it is behind and should get a snapshot instead
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SendError {
+    Closed,
+    Full,
+}
+
+//Subscriber trait, anything a Leaf can push its ServerChanges into
+//implementations must not wait on the network, process_change calls them with the Leaf locked
+pub trait Subscriber: Debug + Send + Sync {
+    /// Hands a change to the subscriber
+    fn send(&self, change: &ServerChange) -> Result<(), SendError>;
+
+    /// Replaces whatever the subscriber still holds for subscription_id with a snapshot,
+    /// called after send came back Full
+    fn replace_pending(&self, _subscription_id: u64, snapshot: &ServerChange) -> Result<(), SendError> {
+        self.send(snapshot)
+    }
+}
@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use rustls_pemfile::Item;
+
+/// Builds a TLS acceptor for wss:// from a PEM certificate chain and a PEM private key,
+/// the key can be PKCS#8, RSA or EC, a self-signed certificate is fine for local use
+pub fn acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor, String> {
+    let certs = load_certs(cert_path)?;
+    let key = load_key(key_path)?;
+
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|err| format!("{} and {}: {}", cert_path, key_path, err))?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+//every certificate in the file, leaf first
+fn load_certs(path: &str) -> Result<Vec<Certificate>, String> {
+    let file = File::open(path).map_err(|err| format!("can't read {}: {}", path, err))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file)).map_err(|err| format!("{}: {}", path, err))?;
+
+    if certs.is_empty() {
+        return Err(format!("{}: no certificates", path));
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+//the first private key in the file
+fn load_key(path: &str) -> Result<PrivateKey, String> {
+    let file = File::open(path).map_err(|err| format!("can't read {}: {}", path, err))?;
+    let mut reader = BufReader::new(file);
+
+    loop {
+        match rustls_pemfile::read_one(&mut reader).map_err(|err| format!("{}: {}", path, err))? {
+            Some(Item::PKCS8Key(key)) | Some(Item::RSAKey(key)) | Some(Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => return Err(format!("{}: no private key", path)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    //a certificate for localhost and its key, written to cert.pem and key.pem in dir
+    fn write_self_signed(dir: &Path) -> (String, String) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let cert_path = dir.join("cert.pem").to_str().unwrap().to_owned();
+        let key_path = dir.join("key.pem").to_str().unwrap().to_owned();
+
+        fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+
+        (cert_path, key_path)
+    }
+
+    #[test]
+    fn acceptor_loads_a_self_signed_cert() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_path, key_path) = write_self_signed(dir.path());
+
+        assert!(acceptor(&cert_path, &key_path).is_ok());
+    }
+
+    #[test]
+    fn missing_key_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_path, _) = write_self_signed(dir.path());
+        let missing = dir.path().join("missing.pem");
+
+        let err = acceptor(&cert_path, missing.to_str().unwrap()).err().unwrap();
+        assert!(err.starts_with("can't read"), "{}", err);
+    }
+
+    #[test]
+    fn garbage_key_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_path, key_path) = write_self_signed(dir.path());
+
+        fs::write(&key_path, "not a key\n").unwrap();
+        let err = acceptor(&cert_path, &key_path).err().unwrap();
+        assert!(err.ends_with("no private key"), "{}", err);
+
+        //a PEM block that isn't a key doesn't count either
+        fs::copy(&cert_path, &key_path).unwrap();
+        assert!(acceptor(&cert_path, &key_path).is_err());
+    }
+
+    #[test]
+    fn missing_cert_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, key_path) = write_self_signed(dir.path());
+        let missing = dir.path().join("missing.pem");
+
+        assert!(acceptor(missing.to_str().unwrap(), &key_path).is_err());
+    }
+}
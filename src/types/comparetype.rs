@@ -0,0 +1,53 @@
+use std::cmp::Ordering;
+use crate::types::datatype::DataType;
+
+//Comparison for Selection, compares a column against the condition
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum CompareType {
+    EQ,
+    NE,
+    LT,
+    LE,
+    GT,
+    GE,
+}
+
+//CompareType functions
+impl CompareType {
+    /// SQL semantics, comparing against NULL never passes
+    pub fn test(&self, datum: &DataType, condition: &DataType) -> bool {
+        match datum.sql_cmp(condition) {
+            None => false,
+            Some(order) => match self {
+                CompareType::EQ => order == Ordering::Equal,
+                CompareType::NE => order != Ordering::Equal,
+                CompareType::LT => order == Ordering::Less,
+                CompareType::LE => order != Ordering::Greater,
+                CompareType::GT => order == Ordering::Greater,
+                CompareType::GE => order != Ordering::Less,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::float::Float;
+
+    #[test]
+    fn eq_never_matches_null() {
+        assert!(!CompareType::EQ.test(&DataType::None, &DataType::None));
+        assert!(!CompareType::NE.test(&DataType::None, &DataType::Int(1)));
+        assert!(!CompareType::EQ.test(&DataType::Int(1), &DataType::None));
+    }
+
+    #[test]
+    fn numbers_match_by_value_across_types() {
+        assert!(CompareType::EQ.test(&DataType::Int(5), &DataType::Float(Float(5.0))));
+        assert!(CompareType::LT.test(&DataType::BigInt(9007199254740992), &DataType::BigInt(9007199254740993)));
+        assert!(CompareType::GT.test(&DataType::BigInt(9007199254740993), &DataType::Float(Float(9007199254740992.0))));
+        assert!(!CompareType::EQ.test(&DataType::BigInt(9007199254740993), &DataType::Float(Float(9007199254740992.0))));
+    }
+}
@@ -1,7 +1,17 @@
 use std::fmt;
+use std::cmp::Ordering;
 use wasm_bindgen::JsValue;
+use wasm_bindgen::JsCast;
+use js_sys::{Date, Uint8Array};
+use crate::types::float::Float;
+use crate::types::decimal::Decimal;
+
+//JS numbers are only exact integers up to 2^53
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
 
 //Data
+//Timestamp is milliseconds since the Unix epoch, Bytes go over the wire as an array of numbers
+//Decimal goes over the wire as a string so no precision is lost
 #[derive(Debug)]
 #[derive(Clone, Hash, Eq, PartialEq)]
 #[derive(Serialize, Deserialize)]
@@ -9,29 +19,35 @@ use wasm_bindgen::JsValue;
 pub enum DataType {
     None,
     Int(i32),
-    Text(String)
+    Text(String),
+    Float(Float),
+    Bool(bool),
+    BigInt(i64),
+    Timestamp(i64),
+    Bytes(Vec<u8>),
+    Decimal(Decimal),
 }
 
 //from conversion, JsValue->DataType
 impl From<JsValue> for DataType {
     fn from(item: JsValue) -> Self {
-        if (item).as_f64().is_some()  {
-            DataType::Int(item.as_f64().unwrap() as i32)
-        } else if ( item).as_string().is_some()  {
-            DataType::Text(item.as_string().unwrap())
-        } else {
-            DataType::None
-        }
+        DataType::from(&item)
     }
 }
 
-//from conversion, f64->SchemaType
+//from conversion, &JsValue->DataType
 impl From<&JsValue> for DataType {
     fn from(item: &JsValue) -> Self {
-        if (*item).as_f64().is_some()  {
-            DataType::Int(item.as_f64().unwrap() as i32)
-        } else if (*item).as_string().is_some()  {
-            DataType::Text(item.as_string().unwrap())
+        if let Some(n) = item.as_f64() {
+            DataType::from_js_number(n)
+        } else if let Some(s) = item.as_string() {
+            DataType::Text(s)
+        } else if let Some(b) = item.as_bool() {
+            DataType::Bool(b)
+        } else if let Some(date) = item.dyn_ref::<Date>() {
+            DataType::Timestamp(date.get_time() as i64)
+        } else if let Some(array) = item.dyn_ref::<Uint8Array>() {
+            DataType::Bytes(array.to_vec())
         } else {
             DataType::None
         }
@@ -46,7 +62,350 @@ impl fmt::Display for DataType {
             DataType::Text(n) => {
                 write!(f, "{}", n)
             }
-            DataType::Int(n) => write!(f, "{}", n)
+            DataType::Int(n) => write!(f, "{}", n),
+            DataType::Float(n) => write!(f, "{}", n),
+            DataType::Bool(n) => write!(f, "{}", n),
+            DataType::BigInt(n) => write!(f, "{}", n),
+            DataType::Timestamp(n) => write!(f, "{}ms", n),
+            DataType::Decimal(n) => write!(f, "{}", n),
+            DataType::Bytes(bytes) => {
+                write!(f, "0x")?;
+                for byte in bytes {
+                    write!(f, "{:02x}", byte)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+//DataType functions, conversions
+impl DataType {
+    /// Converts a JS number, whole numbers become Int, or BigInt past i32, anything fractional stays a Float
+    pub fn from_js_number(n: f64) -> DataType {
+        if n.fract() != 0.0 || !n.is_finite() || n.abs() > MAX_SAFE_INTEGER {
+            DataType::Float(Float(n))
+        } else if n >= i32::MIN as f64 && n <= i32::MAX as f64 {
+            DataType::Int(n as i32)
+        } else {
+            DataType::BigInt(n as i64)
+        }
+    }
+}
+
+//DataType functions, arithmetic used by Aggregation
+//NULL acts as the empty sum, mismatched types and overflow return None
+impl DataType {
+    /// Widens Int to BigInt so sums of Int columns don't overflow
+    pub fn widened(&self) -> DataType {
+        match self {
+            DataType::Int(n) => DataType::BigInt(*n as i64),
+            other => other.clone(),
+        }
+    }
+
+    /// Adds two numbers of the same type
+    pub fn checked_add(&self, other: &DataType) -> Option<DataType> {
+        match (self, other) {
+            (DataType::None, n) | (n, DataType::None) => Some(n.clone()),
+            (DataType::Int(a), DataType::Int(b)) => a.checked_add(*b).map(DataType::Int),
+            (DataType::BigInt(a), DataType::BigInt(b)) => a.checked_add(*b).map(DataType::BigInt),
+            (DataType::Float(a), DataType::Float(b)) => Some(DataType::Float(Float(a.0 + b.0))),
+            (DataType::Decimal(a), DataType::Decimal(b)) => a.checked_add(b).map(DataType::Decimal),
+            _ => None,
+        }
+    }
+
+    /// Subtracts a number of the same type, subtracting NULL is a no-op
+    pub fn checked_sub(&self, other: &DataType) -> Option<DataType> {
+        match (self, other) {
+            (n, DataType::None) => Some(n.clone()),
+            (DataType::Int(a), DataType::Int(b)) => a.checked_sub(*b).map(DataType::Int),
+            (DataType::BigInt(a), DataType::BigInt(b)) => a.checked_sub(*b).map(DataType::BigInt),
+            (DataType::Float(a), DataType::Float(b)) => Some(DataType::Float(Float(a.0 - b.0))),
+            (DataType::Decimal(a), DataType::Decimal(b)) => a.checked_sub(b).map(DataType::Decimal),
+            _ => None,
+        }
+    }
+
+    /// Divides a sum by a count, Decimals keep their scale and everything else becomes a Float
+    pub fn checked_avg(&self, count: i64) -> Option<DataType> {
+        if count == 0 {
+            return Some(DataType::None);
+        }
+
+        match self {
+            DataType::None => Some(DataType::None),
+            DataType::Int(n) => Some(DataType::Float(Float(*n as f64 / count as f64))),
+            DataType::BigInt(n) => Some(DataType::Float(Float(*n as f64 / count as f64))),
+            DataType::Float(n) => Some(DataType::Float(Float(n.0 / count as f64))),
+            DataType::Decimal(n) => n.checked_div_int(count).map(DataType::Decimal),
+            _ => None,
+        }
+    }
+}
+
+//total order across variants
+//None < Bool < numbers < Timestamp < Text < Bytes, numbers of any type compare by value
+//and equal values of different types break the tie Int < BigInt < Decimal < Float
+impl Ord for DataType {
+    fn cmp(&self, other: &DataType) -> Ordering {
+        match (self, other) {
+            (DataType::None, DataType::None) => Ordering::Equal,
+            (DataType::Bool(a), DataType::Bool(b)) => a.cmp(b),
+            (DataType::Timestamp(a), DataType::Timestamp(b)) => a.cmp(b),
+            (DataType::Text(a), DataType::Text(b)) => a.cmp(b),
+            (DataType::Bytes(a), DataType::Bytes(b)) => a.cmp(b),
+            (DataType::Float(a), DataType::Float(b)) => a.cmp(b),
+            _ => match self.numeric_cmp(other) {
+                Some(order) => order.then(self.rank().cmp(&other.rank())),
+                None => self.rank().cmp(&other.rank()),
+            },
+        }
+    }
+}
+
+impl PartialOrd for DataType {
+    fn partial_cmp(&self, other: &DataType) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+//DataType functions, comparisons
+impl DataType {
+    //position of the variant in the cross type order
+    fn rank(&self) -> u8 {
+        match self {
+            DataType::None => 0,
+            DataType::Bool(_) => 1,
+            DataType::Int(_) => 2,
+            DataType::BigInt(_) => 3,
+            DataType::Decimal(_) => 4,
+            DataType::Float(_) => 5,
+            DataType::Timestamp(_) => 6,
+            DataType::Text(_) => 7,
+            DataType::Bytes(_) => 8,
+        }
+    }
+
+    //exact for every pair of numbers, integers and Decimals are never rounded to compare with a Float
+    //canonical so every NaN sorts above infinity here too, whatever its sign bit
+    fn numeric_cmp(&self, other: &DataType) -> Option<Ordering> {
+        match (self, other) {
+            (DataType::Float(a), DataType::Float(b)) => {
+                let (a, b) = (a.canonical(), b.canonical());
+                Some(a.partial_cmp(&b).unwrap_or_else(|| a.total_cmp(&b)))
+            },
+            (DataType::Float(_), _) => other.numeric_cmp(self).map(Ordering::reverse),
+            (_, DataType::Float(b)) => Some(self.as_decimal()?.cmp_f64(b.canonical())),
+            _ => Some(self.as_decimal()?.cmp(&other.as_decimal()?)),
+        }
+    }
+
+    fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            DataType::Int(n) => Some(Decimal::from(*n as i64)),
+            DataType::BigInt(n) => Some(Decimal::from(*n)),
+            DataType::Decimal(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == DataType::None
+    }
+
+    /// SQL comparison, None if either side is NULL, numbers of different types compare by value
+    pub fn sql_cmp(&self, other: &DataType) -> Option<Ordering> {
+        if self.is_null() || other.is_null() {
+            return None;
+        }
+
+        match self.numeric_cmp(other) {
+            Some(order) => Some(order),
+            None => Some(self.cmp(other)),
+        }
+    }
+
+    /// SQL equality, None if either side is NULL
+    pub fn sql_eq(&self, other: &DataType) -> Option<bool> {
+        self.sql_cmp(other).map(|order| order == Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn float(n: f64) -> DataType {
+        DataType::Float(Float(n))
+    }
+
+    fn decimal(text: &str) -> DataType {
+        DataType::Decimal(text.parse().unwrap())
+    }
+
+    #[test]
+    fn numbers_of_different_types_compare_by_value() {
+        assert!(DataType::Int(5) < DataType::BigInt(6));
+        assert!(DataType::BigInt(6) < decimal("6.5"));
+        assert!(decimal("6.5") < float(7.0));
+        assert!(float(-1.5) < DataType::Int(-1));
+        assert!(DataType::BigInt(i64::MAX) > float(1.0e18));
+    }
+
+    #[test]
+    fn equal_values_of_different_types_break_ties_by_type() {
+        assert!(DataType::Int(5) < DataType::BigInt(5));
+        assert!(DataType::BigInt(5) < decimal("5.00"));
+        assert!(decimal("5.00") < float(5.0));
+        assert_ne!(DataType::Int(5), float(5.0));
+    }
+
+    #[test]
+    fn variants_sort_in_the_cross_type_order() {
+        let sorted = [
+            DataType::None,
+            DataType::Bool(true),
+            DataType::Int(1000),
+            DataType::Timestamp(0),
+            DataType::Text("a".to_owned()),
+            DataType::Bytes(vec![0]),
+        ];
+
+        for pair in sorted.windows(2) {
+            assert!(pair[0] < pair[1], "{:?} < {:?}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn nan_of_either_sign_sorts_above_infinity() {
+        let negative_nan = float(f64::INFINITY + f64::NEG_INFINITY);
+        assert!(negative_nan.to_string().contains("NaN"));
+
+        for nan in &[float(f64::NAN), float(-f64::NAN), negative_nan] {
+            assert!(DataType::Int(5) < float(f64::INFINITY));
+            assert!(float(f64::INFINITY) < *nan);
+            assert!(DataType::Int(5) < *nan);
+            assert!(decimal("1000") < *nan);
+            assert_eq!(*nan, float(f64::NAN));
+        }
+    }
+
+    #[test]
+    fn order_is_transitive_across_types() {
+        let values = [
+            DataType::None,
+            DataType::Bool(false),
+            DataType::Int(-3),
+            DataType::Int(5),
+            DataType::BigInt(5),
+            decimal("-3.0"),
+            decimal("5.01"),
+            decimal("9007199254740992.9"),
+            DataType::BigInt(9007199254740993),
+            DataType::BigInt(9007199254740992),
+            DataType::Int(i32::MAX),
+            float(-0.0),
+            float(0.0),
+            float(5.0),
+            float(9007199254740992.0),
+            float(9007199254740994.0),
+            float(f64::NEG_INFINITY),
+            float(f64::INFINITY),
+            float(f64::NAN),
+            float(-f64::NAN),
+            DataType::Timestamp(5),
+            DataType::Text("5".to_owned()),
+        ];
+
+        for a in &values {
+            for b in &values {
+                assert_eq!(a.cmp(b), b.cmp(a).reverse(), "{:?} {:?}", a, b);
+
+                for c in &values {
+                    if a <= b && b <= c {
+                        assert!(a <= c, "{:?} <= {:?} <= {:?}", a, b, c);
+                    }
+                }
+            }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sql_cmp_is_unknown_with_null() {
+        assert_eq!(DataType::None.sql_cmp(&DataType::Int(1)), None);
+        assert_eq!(DataType::Int(1).sql_cmp(&DataType::None), None);
+        assert_eq!(DataType::None.sql_cmp(&DataType::None), None);
+        assert_eq!(DataType::None.sql_eq(&DataType::None), None);
+    }
+
+    #[test]
+    fn sql_cmp_ignores_the_type_tie_break() {
+        assert_eq!(DataType::Int(5).sql_cmp(&float(5.0)), Some(Ordering::Equal));
+        assert_eq!(DataType::BigInt(5).sql_eq(&decimal("5.000")), Some(true));
+        assert_eq!(decimal("1.5").sql_cmp(&DataType::Int(2)), Some(Ordering::Less));
+        assert_eq!(DataType::Text("b".to_owned()).sql_cmp(&DataType::Text("a".to_owned())), Some(Ordering::Greater));
+    }
+
+    #[test]
+    fn integers_past_2_pow_53_are_not_rounded_to_floats() {
+        let two_pow_53 = float(9007199254740992.0);
+
+        assert_eq!(DataType::BigInt(9007199254740993).sql_eq(&two_pow_53), Some(false));
+        assert_eq!(DataType::BigInt(9007199254740993).sql_cmp(&two_pow_53), Some(Ordering::Greater));
+        assert_eq!(DataType::BigInt(9007199254740992).sql_eq(&two_pow_53), Some(true));
+        assert_eq!(decimal("9007199254740992.9").sql_cmp(&two_pow_53), Some(Ordering::Greater));
+        assert_eq!(float(0.1).sql_cmp(&decimal("0.1")), Some(Ordering::Greater));
+    }
+
+    fn round_trip(datum: &DataType) -> DataType {
+        serde_json::from_str(&serde_json::to_string(datum).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn new_types_survive_serde() {
+        let values = [
+            float(-1.5),
+            float(f64::MAX),
+            DataType::Bool(true),
+            DataType::BigInt(i64::MIN),
+            DataType::Timestamp(1_600_000_000_000),
+            DataType::Bytes(vec![0, 127, 255]),
+            decimal("-12.340"),
+        ];
+
+        for datum in &values {
+            assert_eq!(round_trip(datum), *datum);
+        }
+
+        assert_eq!(serde_json::to_string(&DataType::Bytes(vec![1, 2])).unwrap(), r#"{"t":"Bytes","c":[1,2]}"#);
+    }
+
+    #[test]
+    fn non_finite_floats_go_over_the_wire_as_strings() {
+        assert_eq!(serde_json::to_string(&float(f64::NAN)).unwrap(), r#"{"t":"Float","c":"NaN"}"#);
+        assert_eq!(serde_json::to_string(&float(f64::INFINITY)).unwrap(), r#"{"t":"Float","c":"inf"}"#);
+        assert_eq!(serde_json::to_string(&float(f64::NEG_INFINITY)).unwrap(), r#"{"t":"Float","c":"-inf"}"#);
+
+        for n in &[f64::NAN, -f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(round_trip(&float(*n)), float(*n));
+        }
+
+        assert_eq!(serde_json::from_str::<DataType>(r#"{"t":"Float","c":2}"#).unwrap(), float(2.0));
+        assert!(serde_json::from_str::<DataType>(r#"{"t":"Float","c":"infinity"}"#).is_err());
+    }
+
+    #[test]
+    fn js_numbers_pick_the_narrowest_type() {
+        assert_eq!(DataType::from_js_number(5.0), DataType::Int(5));
+        assert_eq!(DataType::from_js_number(-2147483648.0), DataType::Int(i32::MIN));
+        assert_eq!(DataType::from_js_number(2147483648.0), DataType::BigInt(2147483648));
+        assert_eq!(DataType::from_js_number(MAX_SAFE_INTEGER), DataType::BigInt(9007199254740991));
+        assert_eq!(DataType::from_js_number(MAX_SAFE_INTEGER + 1.0), float(MAX_SAFE_INTEGER + 1.0));
+        assert_eq!(DataType::from_js_number(1.5), float(1.5));
+        assert_eq!(DataType::from_js_number(f64::INFINITY), float(f64::INFINITY));
+        assert_eq!(DataType::from_js_number(f64::NAN), float(f64::NAN));
+    }
+}
@@ -0,0 +1,431 @@
+use std::fmt;
+use std::str::FromStr;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use std::cmp::Ordering;
+
+//largest number of digits kept after the decimal point
+const MAX_SCALE: u32 = 28;
+
+//Decimal, fixed precision number for money columns, value is mantissa / 10^scale
+//equality ignores trailing zeros, so 1.50 == 1.5, goes over the wire as a string
+#[derive(Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+//Decimal functions
+impl Decimal {
+    //constructor
+    pub fn new(mantissa: i128, scale: u32) -> Decimal {
+        Decimal { mantissa, scale }
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Returns the Decimal without trailing zeros after the point
+    pub fn normalized(&self) -> Decimal {
+        let mut mantissa = self.mantissa;
+        let mut scale = self.scale;
+
+        while scale > 0 && mantissa % 10 == 0 {
+            mantissa /= 10;
+            scale -= 1;
+        }
+
+        Decimal { mantissa, scale }
+    }
+
+    /// Returns the Decimal with exactly scale digits after the point, rounding half away from zero
+    pub fn rescale(&self, scale: u32) -> Option<Decimal> {
+        if scale > MAX_SCALE {
+            return None;
+        }
+
+        if scale >= self.scale {
+            let factor = 10i128.checked_pow(scale - self.scale)?;
+            return Some(Decimal::new(self.mantissa.checked_mul(factor)?, scale));
+        }
+
+        let factor = 10i128.pow(self.scale - scale);
+        Some(Decimal::new(round_div(self.mantissa, factor), scale))
+    }
+
+    /// Exact sum, scale of the result is the larger of the two
+    pub fn checked_add(&self, other: &Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        let left = self.rescale(scale)?;
+        let right = other.rescale(scale)?;
+
+        Some(Decimal::new(left.mantissa.checked_add(right.mantissa)?, scale))
+    }
+
+    /// Exact difference, scale of the result is the larger of the two
+    pub fn checked_sub(&self, other: &Decimal) -> Option<Decimal> {
+        self.checked_add(&Decimal::new(other.mantissa.checked_neg()?, other.scale))
+    }
+
+    /// Divides by a whole number keeping the current scale, rounding half away from zero
+    pub fn checked_div_int(&self, divisor: i64) -> Option<Decimal> {
+        if divisor == 0 {
+            return None;
+        }
+
+        Some(Decimal::new(round_div(self.mantissa, divisor as i128), self.scale))
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.mantissa as f64 / 10f64.powi(self.scale as i32)
+    }
+
+    /// Exact comparison with a float, NaN and infinity sort above every Decimal, -infinity below
+    pub fn cmp_f64(&self, other: f64) -> Ordering {
+        if other.is_nan() || other >= I128_BOUND {
+            return Ordering::Less;
+        }
+
+        if other <= -I128_BOUND {
+            return Ordering::Greater;
+        }
+
+        //both split into a whole part and a fraction under 1 with the same sign, whole parts first
+        let whole = other.trunc();
+        let (self_whole, self_fraction) = self.split();
+
+        self_whole.cmp(&(whole as i128)).then_with(|| fraction_cmp(self_fraction, self.scale, other - whole))
+    }
+
+    //whole part and what's left after the point, in units of 10^-scale
+    fn split(&self) -> (i128, i128) {
+        let unit = 10i128.pow(self.scale);
+
+        (self.mantissa / unit, self.mantissa % unit)
+    }
+}
+
+//every Decimal is smaller than this in magnitude, so floats past it needn't be looked at closely
+const I128_BOUND: f64 = 170141183460469231731687303715884105728.0;
+
+//compares numerator / 10^scale against fraction, both under 1 in magnitude
+fn fraction_cmp(numerator: i128, scale: u32, fraction: f64) -> Ordering {
+    let sign = if fraction > 0.0 { 1 } else if fraction < 0.0 { -1 } else { 0 };
+
+    match numerator.signum().cmp(&sign) {
+        Ordering::Equal if sign == 0 => Ordering::Equal,
+        Ordering::Equal if sign < 0 => magnitude_cmp(numerator.unsigned_abs(), scale, -fraction).reverse(),
+        Ordering::Equal => magnitude_cmp(numerator.unsigned_abs(), scale, fraction),
+        order => order,
+    }
+}
+
+//compares numerator / 10^scale against a positive fraction under 1 by putting the fraction
+//over 10^scale too, fraction is bits * 2^-shift so that's bits * 5^scale / 2^(shift - scale)
+fn magnitude_cmp(numerator: u128, scale: u32, fraction: f64) -> Ordering {
+    let raw = fraction.to_bits();
+    let exponent = ((raw >> 52) & 0x7ff) as u32;
+    let (bits, shift) = if exponent == 0 {
+        (raw & ((1 << 52) - 1), 1074)
+    } else {
+        (raw & ((1 << 52) - 1) | 1 << 52, 1075 - exponent)
+    };
+
+    //under 1 means shift is past 52, so past scale and no bits are lost before the shift
+    let scaled = bits as u128 * 5u128.pow(scale);
+    let down = shift - scale;
+    let (floor, exact) = if down >= 128 {
+        (0, false)
+    } else {
+        (scaled >> down, scaled & ((1 << down) - 1) == 0)
+    };
+
+    match numerator.cmp(&floor) {
+        Ordering::Equal if !exact => Ordering::Less,
+        order => order,
+    }
+}
+
+//integer division rounding half away from zero
+fn round_div(numerator: i128, divisor: i128) -> i128 {
+    let quotient = numerator / divisor;
+    let remainder = numerator % divisor;
+
+    if remainder.abs() * 2 >= divisor.abs() {
+        if (numerator < 0) == (divisor < 0) {
+            quotient + 1
+        } else {
+            quotient - 1
+        }
+    } else {
+        quotient
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(item: i64) -> Self {
+        Decimal::new(item as i128, 0)
+    }
+}
+
+//parses "-12.340" style strings, no exponents
+impl FromStr for Decimal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid decimal {:?}", s);
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (whole, fraction) = match digits.find('.') {
+            Some(point) => (&digits[..point], &digits[point + 1..]),
+            None => (digits, ""),
+        };
+
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(err());
+        }
+
+        if fraction.len() > MAX_SCALE as usize {
+            return Err(format!("decimal {:?} has more than {} digits after the point", s, MAX_SCALE));
+        }
+
+        let mut mantissa: i128 = 0;
+
+        for c in whole.chars().chain(fraction.chars()) {
+            let digit = c.to_digit(10).ok_or_else(err)?;
+            mantissa = mantissa.checked_mul(10).and_then(|m| m.checked_add(digit as i128)).ok_or_else(err)?;
+        }
+
+        if negative {
+            mantissa = -mantissa;
+        }
+
+        Ok(Decimal::new(mantissa, fraction.len() as u32))
+    }
+}
+
+impl TryFrom<String> for Decimal {
+    type Error = String;
+
+    fn try_from(item: String) -> Result<Self, Self::Error> {
+        item.parse()
+    }
+}
+
+impl From<Decimal> for String {
+    fn from(item: Decimal) -> Self {
+        item.to_string()
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        let left = self.normalized();
+        let right = other.normalized();
+
+        left.mantissa == right.mantissa && left.scale == right.scale
+    }
+}
+
+impl Eq for Decimal {}
+
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let normalized = self.normalized();
+        normalized.mantissa.hash(state);
+        normalized.scale.hash(state);
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        let scale = self.scale.max(other.scale);
+
+        match (self.rescale(scale), other.rescale(scale)) {
+            (Some(left), Some(right)) => left.mantissa.cmp(&right.mantissa),
+            //too large to line up, whole parts first and then the fractions, which always line up
+            _ => {
+                let (left_whole, left_fraction) = self.split();
+                let (right_whole, right_fraction) = other.split();
+                let left_fraction = Decimal::new(left_fraction, self.scale).rescale(scale).unwrap();
+                let right_fraction = Decimal::new(right_fraction, other.scale).rescale(scale).unwrap();
+
+                left_whole.cmp(&right_whole).then(left_fraction.mantissa.cmp(&right_fraction.mantissa))
+            },
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+
+        let padded = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = padded.split_at(padded.len() - scale);
+
+        write!(f, "{}{}.{}", sign, whole, fraction)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn decimal(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    fn hash(n: &Decimal) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        n.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn parses_sign_point_and_scale() {
+        assert_eq!(decimal("-12.340"), Decimal::new(-12340, 3));
+        assert_eq!(decimal("+7"), Decimal::new(7, 0));
+        assert_eq!(decimal(".5"), Decimal::new(5, 1));
+        assert_eq!(decimal("3."), Decimal::new(3, 0));
+        assert_eq!(decimal("-12.340").scale(), 3);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        for text in &["", "-", ".", "1.2.3", "1e5", "abc", "1,5", "--1"] {
+            assert!(text.parse::<Decimal>().is_err(), "{:?}", text);
+        }
+
+        assert!(format!("0.{}", "1".repeat(29)).parse::<Decimal>().is_err());
+        assert!("9".repeat(40).parse::<Decimal>().is_err());
+    }
+
+    #[test]
+    fn trailing_zeros_compare_and_hash_equal() {
+        assert_eq!(decimal("1.50"), decimal("1.5"));
+        assert_eq!(hash(&decimal("1.50")), hash(&decimal("1.5")));
+        assert_eq!(decimal("0.00"), decimal("0"));
+        assert_eq!(hash(&decimal("-0.0")), hash(&decimal("0")));
+        assert_ne!(decimal("1.51"), decimal("1.5"));
+    }
+
+    #[test]
+    fn orders_across_scales() {
+        assert!(decimal("1.5") < decimal("1.51"));
+        assert!(decimal("-1.5") < decimal("-1.49"));
+        assert!(decimal("10") > decimal("9.999"));
+        assert_eq!(decimal("2.50").cmp(&decimal("2.5")), Ordering::Equal);
+    }
+
+    #[test]
+    fn orders_past_where_scales_line_up() {
+        //too close together for f64 to tell apart, and i128::MAX / 10 + 1 can't be given a point
+        let tenth = Decimal::new(i128::MAX, 1);
+
+        assert_eq!(Decimal::new(i128::MAX / 10 + 1, 0).cmp(&tenth), Ordering::Greater);
+        assert_eq!(tenth.cmp(&Decimal::new(i128::MAX / 10 + 1, 0)), Ordering::Less);
+        assert_eq!(Decimal::new(-(i128::MAX / 10) - 1, 0).cmp(&Decimal::new(-i128::MAX, 1)), Ordering::Less);
+    }
+
+    #[test]
+    fn compares_with_floats_exactly() {
+        assert_eq!(decimal("0.1").cmp_f64(0.1), Ordering::Less);
+        assert_eq!(decimal("0.5").cmp_f64(0.5), Ordering::Equal);
+        assert_eq!(decimal("-0.5").cmp_f64(-0.5), Ordering::Equal);
+        assert_eq!(decimal("-0.1").cmp_f64(-0.1), Ordering::Greater);
+        assert_eq!(decimal("0").cmp_f64(-0.0), Ordering::Equal);
+        assert_eq!(decimal("0").cmp_f64(f64::MIN_POSITIVE / 4.0), Ordering::Less);
+        assert_eq!(decimal("0.0000000000000000000000000001").cmp_f64(1e-300), Ordering::Greater);
+        assert_eq!(decimal("-2.5").cmp_f64(-2.25), Ordering::Less);
+
+        assert_eq!(decimal("9007199254740993").cmp_f64(9007199254740992.0), Ordering::Greater);
+        assert_eq!(decimal("9007199254740992.9").cmp_f64(9007199254740992.0), Ordering::Greater);
+        assert_eq!(Decimal::new(i128::MAX, 0).cmp_f64(1e38), Ordering::Greater);
+        assert_eq!(Decimal::new(i128::MAX, 0).cmp_f64(1e39), Ordering::Less);
+        assert_eq!(Decimal::new(i128::MIN, 0).cmp_f64(-1e39), Ordering::Greater);
+
+        assert_eq!(decimal("1").cmp_f64(f64::INFINITY), Ordering::Less);
+        assert_eq!(decimal("1").cmp_f64(f64::NEG_INFINITY), Ordering::Greater);
+        assert_eq!(decimal("1").cmp_f64(f64::NAN), Ordering::Less);
+    }
+
+    #[test]
+    fn round_div_rounds_half_away_from_zero() {
+        assert_eq!(round_div(5, 2), 3);
+        assert_eq!(round_div(-5, 2), -3);
+        assert_eq!(round_div(5, -2), -3);
+        assert_eq!(round_div(-5, -2), 3);
+        assert_eq!(round_div(4, 3), 1);
+        assert_eq!(round_div(-4, 3), -1);
+        assert_eq!(round_div(-7, 2), -4);
+        assert_eq!(round_div(6, 3), 2);
+    }
+
+    #[test]
+    fn rescale_rounds_and_pads() {
+        assert_eq!(decimal("1.005").rescale(2), Some(Decimal::new(101, 2)));
+        assert_eq!(decimal("-1.005").rescale(2), Some(Decimal::new(-101, 2)));
+        assert_eq!(decimal("-1.004").rescale(2), Some(Decimal::new(-100, 2)));
+        assert_eq!(decimal("-0.5").rescale(0), Some(Decimal::new(-1, 0)));
+        assert_eq!(decimal("1.5").rescale(3).unwrap().to_string(), "1.500");
+        assert_eq!(decimal("1").rescale(MAX_SCALE + 1), None);
+        assert_eq!(Decimal::new(i128::MAX, 0).rescale(1), None);
+    }
+
+    #[test]
+    fn add_and_sub_mix_scales_exactly() {
+        let sum = decimal("0.1").checked_add(&decimal("0.25")).unwrap();
+        assert_eq!(sum.to_string(), "0.35");
+        assert_eq!(sum.scale(), 2);
+
+        let difference = decimal("1").checked_sub(&decimal("1.001")).unwrap();
+        assert_eq!(difference.to_string(), "-0.001");
+
+        assert_eq!(Decimal::new(i128::MAX, 0).checked_add(&decimal("1")), None);
+        assert_eq!(Decimal::new(i128::MIN, 0).checked_sub(&decimal("1")), None);
+    }
+
+    #[test]
+    fn div_int_keeps_scale_and_rounds() {
+        assert_eq!(decimal("10.00").checked_div_int(3).unwrap().to_string(), "3.33");
+        assert_eq!(decimal("-10.00").checked_div_int(3).unwrap().to_string(), "-3.33");
+        assert_eq!(decimal("0.05").checked_div_int(-2).unwrap().to_string(), "-0.03");
+        assert_eq!(decimal("1").checked_div_int(0), None);
+    }
+
+    #[test]
+    fn display_keeps_scale_and_leading_zeros() {
+        assert_eq!(decimal("1.50").to_string(), "1.50");
+        assert_eq!(decimal("-0.05").to_string(), "-0.05");
+        assert_eq!(decimal(".5").to_string(), "0.5");
+        assert_eq!(decimal("-12").to_string(), "-12");
+        assert_eq!(Decimal::new(0, 3).to_string(), "0.000");
+    }
+
+    #[test]
+    fn serializes_as_a_string() {
+        let json = serde_json::to_string(&decimal("-1.50")).unwrap();
+        assert_eq!(json, "\"-1.50\"");
+        assert_eq!(serde_json::from_str::<Decimal>(&json).unwrap().to_string(), "-1.50");
+        assert!(serde_json::from_str::<Decimal>("\"x\"").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use std::cmp::Ordering;
+
+//Float, f64 wrapper with a total order so DataType can stay Hash and Eq
+//-0.0 is treated as 0.0 and every NaN as the same value, NaN sorts above infinity
+//goes over the wire as a number, or as "NaN", "inf" or "-inf" which JSON has no numbers for
+#[derive(Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "FloatRepr", into = "FloatRepr")]
+pub struct Float(pub f64);
+
+//wire form of a Float
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum FloatRepr {
+    Number(f64),
+    Text(String),
+}
+
+impl Float {
+    //canonical form used for comparing and hashing
+    pub(crate) fn canonical(&self) -> f64 {
+        if self.0.is_nan() {
+            f64::NAN
+        } else if self.0 == 0.0 {
+            0.0
+        } else {
+            self.0
+        }
+    }
+}
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Float) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Float {}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Float) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Float {
+    fn cmp(&self, other: &Float) -> Ordering {
+        self.canonical().total_cmp(&other.canonical())
+    }
+}
+
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical().to_bits().hash(state);
+    }
+}
+
+impl TryFrom<FloatRepr> for Float {
+    type Error = String;
+
+    fn try_from(item: FloatRepr) -> Result<Self, Self::Error> {
+        match item {
+            FloatRepr::Number(n) => Ok(Float(n)),
+            FloatRepr::Text(text) => match text.as_str() {
+                "NaN" => Ok(Float(f64::NAN)),
+                "inf" => Ok(Float(f64::INFINITY)),
+                "-inf" => Ok(Float(f64::NEG_INFINITY)),
+                _ => Err(format!("invalid float {:?}", text)),
+            },
+        }
+    }
+}
+
+impl From<Float> for FloatRepr {
+    fn from(item: Float) -> Self {
+        match item.0 {
+            n if n.is_nan() => FloatRepr::Text("NaN".to_owned()),
+            n if n == f64::INFINITY => FloatRepr::Text("inf".to_owned()),
+            n if n == f64::NEG_INFINITY => FloatRepr::Text("-inf".to_owned()),
+            n => FloatRepr::Number(n),
+        }
+    }
+}
+
+impl From<f64> for Float {
+    fn from(item: f64) -> Self {
+        Float(item)
+    }
+}
+
+impl fmt::Display for Float {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
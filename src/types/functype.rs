@@ -1,5 +1,8 @@
-//Supposed to be for Aggregation
+//Aggregation function, SUM and AVG take the index of the column they aggregate
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
 pub enum FuncType {
-    SUM(Vec<usize>),
+    SUM(usize),
+    AVG(usize),
     COUNT
 }
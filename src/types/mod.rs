@@ -1,7 +1,12 @@
 pub mod datatype;
+pub mod decimal;
+pub mod float;
 pub mod changetype;
+pub mod comparetype;
 pub mod schematype;
 pub mod functype;
 pub mod operatortype;
 pub mod permissiontype;
 
+pub mod slowconsumerpolicy;
+pub mod rowpolicy;
@@ -1,3 +1,5 @@
+//PermissionType, what a client path is for
+//Read paths get the client subgraph and a subscription to their Leaf, Write paths send ServerChanges
 #[derive(Debug)]
 #[derive(Clone, Hash, Eq, PartialEq)]
 pub enum PermissionType {
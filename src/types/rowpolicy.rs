@@ -0,0 +1,7 @@
+//RowPolicy, limits which rows of a Leaf a user sees, every policy on a Leaf has to hold
+//ColumnEqualsUser: the column has to equal the caller's user_id, read as the column's type
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowPolicy {
+    ColumnEqualsUser(usize),
+}
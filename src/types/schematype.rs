@@ -1,23 +1,160 @@
 use wasm_bindgen::JsValue;
+use wasm_bindgen::JsCast;
+use js_sys::Array;
+use crate::types::datatype::DataType;
+use crate::types::float::Float;
 
-//Schema, for Views only
+//Schema, for Views and Roots
 #[derive(Debug, Clone, PartialEq)]
 #[derive(Serialize, Deserialize)]
 pub enum SchemaType {
     None,
     Int,
-    Text
+    Text,
+    Float,
+    Bool,
+    BigInt,
+    Timestamp,
+    Bytes,
+    Decimal(u32),
 }
 
 //from conversion, JsValue->SchemaType
+//a type code, or [code, scale] for a Decimal that keeps digits after the point
 impl From<JsValue> for SchemaType {
     fn from(item: JsValue) -> Self {
-        if item == 2 {
-            SchemaType::Text
-        } else if item == 1 {
-            SchemaType::Int
-        } else {
-            SchemaType::None
+        let (code, scale) = match item.dyn_ref::<Array>() {
+            Some(array) => (array.get(0).as_f64(), array.get(1).as_f64()),
+            None => (item.as_f64(), None),
+        };
+
+        match code {
+            Some(code) => SchemaType::from_code(code as i32, scale.unwrap_or(0.0) as u32),
+            None => SchemaType::None,
+        }
+    }
+}
+
+//SchemaType functions
+impl SchemaType {
+    /// Returns the type for a JS type code, scale is only used by Decimal, unknown codes are None
+    pub fn from_code(code: i32, scale: u32) -> SchemaType {
+        match code {
+            1 => SchemaType::Int,
+            2 => SchemaType::Text,
+            3 => SchemaType::Float,
+            4 => SchemaType::Bool,
+            5 => SchemaType::BigInt,
+            6 => SchemaType::Timestamp,
+            7 => SchemaType::Bytes,
+            8 => SchemaType::Decimal(scale),
+            _ => SchemaType::None,
+        }
+    }
+
+    /// Returns whether a DataType is allowed in a column of this type, NULLs always are
+    pub fn matches(&self, datum: &DataType) -> bool {
+        match (self, datum) {
+            (_, DataType::None) => true,
+            //more digits after the point than the column keeps would lose precision
+            (SchemaType::Decimal(scale), DataType::Decimal(n)) => n.scale() <= *scale,
+            _ => matches!((self, datum),
+                (SchemaType::Int, DataType::Int(_))
+                | (SchemaType::Text, DataType::Text(_))
+                | (SchemaType::Float, DataType::Float(_))
+                | (SchemaType::Bool, DataType::Bool(_))
+                | (SchemaType::BigInt, DataType::BigInt(_))
+                | (SchemaType::Timestamp, DataType::Timestamp(_))
+                | (SchemaType::Bytes, DataType::Bytes(_))),
+        }
+    }
+
+    /// Parses plain text, like a URL query value, as a DataType of this type
+    pub fn parse(&self, text: &str) -> Result<DataType, String> {
+        let err = || format!("can't parse {:?} as {:?}", text, self);
+
+        match self {
+            SchemaType::None => Ok(DataType::None),
+            SchemaType::Int => text.parse().map(DataType::Int).map_err(|_| err()),
+            SchemaType::Text => Ok(DataType::Text(text.to_owned())),
+            SchemaType::Float => text.parse().map(|n| DataType::Float(Float(n))).map_err(|_| err()),
+            SchemaType::Bool => text.parse().map(DataType::Bool).map_err(|_| err()),
+            SchemaType::BigInt => text.parse().map(DataType::BigInt).map_err(|_| err()),
+            SchemaType::Timestamp => text.parse().map(DataType::Timestamp).map_err(|_| err()),
+            SchemaType::Decimal(_) => text.parse().map(DataType::Decimal).map_err(|_| err()),
+            //hex, same as DataType's Display
+            SchemaType::Bytes => {
+                let hex = text.strip_prefix("0x").unwrap_or(text);
+
+                if !hex.is_ascii() || hex.len() % 2 != 0 {
+                    return Err(err());
+                }
+
+                (0..hex.len()).step_by(2)
+                    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| err()))
+                    .collect::<Result<Vec<u8>, String>>()
+                    .map(DataType::Bytes)
+            },
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_query_text_by_type() {
+        assert_eq!(SchemaType::Int.parse("-7"), Ok(DataType::Int(-7)));
+        assert_eq!(SchemaType::Text.parse("7"), Ok(DataType::Text("7".to_owned())));
+        assert_eq!(SchemaType::Float.parse("2.5"), Ok(DataType::Float(Float(2.5))));
+        assert_eq!(SchemaType::Float.parse("inf"), Ok(DataType::Float(Float(f64::INFINITY))));
+        assert_eq!(SchemaType::Bool.parse("true"), Ok(DataType::Bool(true)));
+        assert_eq!(SchemaType::BigInt.parse("9007199254740993"), Ok(DataType::BigInt(9007199254740993)));
+        assert_eq!(SchemaType::Timestamp.parse("0"), Ok(DataType::Timestamp(0)));
+        assert_eq!(SchemaType::Decimal(2).parse("1.50"), Ok(DataType::Decimal("1.5".parse().unwrap())));
+        assert_eq!(SchemaType::Bytes.parse("0x00ff"), Ok(DataType::Bytes(vec![0, 255])));
+        assert_eq!(SchemaType::Bytes.parse("7f"), Ok(DataType::Bytes(vec![127])));
+        assert_eq!(SchemaType::None.parse("anything"), Ok(DataType::None));
+    }
+
+    #[test]
+    fn bad_query_text_is_an_error() {
+        assert!(SchemaType::Int.parse("2147483648").is_err());
+        assert!(SchemaType::Int.parse("1.5").is_err());
+        assert!(SchemaType::Float.parse("x").is_err());
+        assert!(SchemaType::Bool.parse("1").is_err());
+        assert!(SchemaType::Decimal(2).parse("1e5").is_err());
+        assert!(SchemaType::Bytes.parse("0x0").is_err());
+        assert!(SchemaType::Bytes.parse("zz").is_err());
+        assert!(SchemaType::Bytes.parse("é0").is_err());
+    }
+
+    #[test]
+    fn type_codes_map_to_types() {
+        let codes = [
+            (1, SchemaType::Int),
+            (2, SchemaType::Text),
+            (3, SchemaType::Float),
+            (4, SchemaType::Bool),
+            (5, SchemaType::BigInt),
+            (6, SchemaType::Timestamp),
+            (7, SchemaType::Bytes),
+            (8, SchemaType::Decimal(2)),
+            (0, SchemaType::None),
+            (9, SchemaType::None),
+        ];
+
+        for (code, schema_type) in codes.iter() {
+            assert_eq!(SchemaType::from_code(*code, 2), *schema_type);
+        }
+    }
+
+    #[test]
+    fn decimal_columns_refuse_extra_digits() {
+        assert!(SchemaType::Decimal(2).matches(&DataType::Decimal("1.25".parse().unwrap())));
+        assert!(!SchemaType::Decimal(2).matches(&DataType::Decimal("1.255".parse().unwrap())));
+        assert!(SchemaType::Decimal(2).matches(&DataType::None));
+        assert!(!SchemaType::Decimal(2).matches(&DataType::Float(Float(1.25))));
+    }
+}
@@ -0,0 +1,24 @@
+//SlowConsumerPolicy, what happens to a subscriber whose outgoing queue is full
+//Drop: the client is disconnected, it can resume from its last seq when it reconnects
+//Coalesce: everything still queued for the subscription is replaced by a fresh snapshot
+//Block: the Leaf waits for room, holding up the write that produced the change
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum SlowConsumerPolicy {
+    Drop,
+    Coalesce,
+    Block,
+}
+
+impl std::str::FromStr for SlowConsumerPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<SlowConsumerPolicy, String> {
+        match text.to_lowercase().as_str() {
+            "drop" => Ok(SlowConsumerPolicy::Drop),
+            "coalesce" => Ok(SlowConsumerPolicy::Coalesce),
+            "block" => Ok(SlowConsumerPolicy::Block),
+            _ => Err(format!("unknown slow consumer policy {}", text)),
+        }
+    }
+}
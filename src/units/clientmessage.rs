@@ -0,0 +1,60 @@
+use crate::units::readrequest::ReadRequest;
+use crate::units::serverchange::ServerChange;
+use crate::units::subscription::{SubscribeRequest, UnsubscribeRequest};
+
+//ClientMessage, everything a client can send over its websocket, tagged like DataType
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(tag = "t", content = "c")]
+pub enum ClientMessage {
+    Write(ServerChange),
+    Read(Box<ReadRequest>),
+    Subscribe(SubscribeRequest),
+    Unsubscribe(UnsubscribeRequest),
+}
+
+//ClientMessage functions
+impl ClientMessage {
+    /// Parses a message, a bare ServerChange from older clients is taken as a Write
+    pub fn parse(json: &str) -> Result<ClientMessage, serde_json::Error> {
+        match serde_json::from_str::<ClientMessage>(json) {
+            Ok(message) => Ok(message),
+            Err(err) => match serde_json::from_str::<ServerChange>(json) {
+                Ok(sc) => Ok(ClientMessage::Write(sc)),
+                Err(_) => Err(err),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::leaf::PRIMARY_INDEX;
+    use crate::types::datatype::DataType;
+
+    #[test]
+    fn read_request_fills_in_defaults() {
+        let json = r#"{"t":"Read","c":{"request_id":7,"leaf":"/stories","keys":[{"t":"Int","c":1},{"t":"Int","c":2}]}}"#;
+        let expected = ReadRequest { request_id: 7, leaf: "/stories".to_owned(), index: PRIMARY_INDEX.to_owned(),
+            keys: vec![DataType::Int(1), DataType::Int(2)], range: None };
+
+        assert_eq!(ClientMessage::parse(json).unwrap(), ClientMessage::Read(Box::new(expected)));
+    }
+
+    #[test]
+    fn malformed_read_requests_are_errors() {
+        //no leaf, a key that isn't a DataType, not JSON at all
+        assert!(ClientMessage::parse(r#"{"t":"Read","c":{"request_id":7}}"#).is_err());
+        assert!(ClientMessage::parse(r#"{"t":"Read","c":{"request_id":7,"leaf":"/stories","keys":[1]}}"#).is_err());
+        assert!(ClientMessage::parse(r#"{"t":"Read","c":{"request_id":7,"leaf":"/stories""#).is_err());
+        assert!(ClientMessage::parse(r#"{"t":"Lookup","c":{}}"#).is_err());
+    }
+
+    #[test]
+    fn bare_server_change_is_a_write() {
+        let json = r#"{"root_id":"Stories","changes":[]}"#;
+
+        assert_eq!(ClientMessage::parse(json).unwrap(), ClientMessage::Write(ServerChange::new("Stories".to_owned(), Vec::new())));
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+
+//Grant, what a client is allowed to touch
+//read: paths of the Leaves it can read and subscribe to, write: root_ids of the Roots it can write
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Grant {
+    #[serde(default)]
+    pub read: HashSet<String>,
+    #[serde(default)]
+    pub write: HashSet<String>,
+}
+
+//Grant functions
+impl Grant {
+    //constructor
+    pub fn new(read: &[&str], write: &[&str]) -> Grant {
+        Grant {
+            read: read.iter().map(|path| path.to_string()).collect(),
+            write: write.iter().map(|root_id| root_id.to_string()).collect(),
+        }
+    }
+
+    /// Errors unless the Leaf at leaf_path can be read
+    pub fn check_read(&self, leaf_path: &str) -> Result<(), String> {
+        if self.read.contains(leaf_path) {
+            Ok(())
+        } else {
+            Err(format!("not allowed to read {}", leaf_path))
+        }
+    }
+
+    /// Errors unless the Root root_id can be written
+    pub fn check_write(&self, root_id: &str) -> Result<(), String> {
+        if self.write.contains(root_id) {
+            Ok(())
+        } else {
+            Err(format!("not allowed to write {}", root_id))
+        }
+    }
+
+    /// Returns what both grants allow
+    pub fn intersect(&self, other: &Grant) -> Grant {
+        Grant {
+            read: self.read.intersection(&other.read).cloned().collect(),
+            write: self.write.intersection(&other.write).cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_and_writes_are_checked_separately() {
+        let grant = Grant::new(&["/stories"], &["Votes"]);
+
+        assert!(grant.check_read("/stories").is_ok());
+        assert!(grant.check_write("Votes").is_ok());
+
+        //a leaf path doesn't give write on a root and the other way around
+        assert_eq!(grant.check_read("/votes").unwrap_err(), "not allowed to read /votes");
+        assert_eq!(grant.check_write("Stories").unwrap_err(), "not allowed to write Stories");
+        assert!(grant.check_read("Votes").is_err());
+        assert!(grant.check_write("/stories").is_err());
+    }
+
+    #[test]
+    fn empty_grant_allows_nothing() {
+        let grant = Grant::default();
+
+        assert!(grant.check_read("/stories").is_err());
+        assert!(grant.check_write("Stories").is_err());
+    }
+
+    #[test]
+    fn intersect_keeps_what_both_allow() {
+        let path_grant = Grant::new(&["/stories", "/votes"], &["Stories", "Votes"]);
+        let token_grant = Grant::new(&["/votes", "/users"], &["Stories"]);
+
+        let both = path_grant.intersect(&token_grant);
+        assert_eq!(both, Grant::new(&["/votes"], &["Stories"]));
+        assert_eq!(both, token_grant.intersect(&path_grant));
+
+        assert_eq!(path_grant.intersect(&Grant::default()), Grant::default());
+        assert_eq!(path_grant.intersect(&path_grant), path_grant);
+    }
+}
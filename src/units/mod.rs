@@ -1,3 +1,12 @@
 pub mod change;
+pub mod clientmessage;
+pub mod rangequery;
+pub mod readrequest;
 pub mod row;
-pub mod serverchange;
\ No newline at end of file
+pub mod schema;
+pub mod serverchange;
+pub mod servererror;
+pub mod subscription;
+pub mod writeack;
+pub mod grant;
+pub mod rowfilter;
@@ -0,0 +1,24 @@
+use crate::types::datatype::DataType;
+
+fn return_false() -> bool {
+    false
+}
+
+//RangeQuery, read of an ordered index
+//start: inclusive lower key, end: exclusive upper key, either left out is unbounded
+//after: cursor, only keys past it in the direction read, for "first N after key X" pages
+//limit: max rows returned, rows sharing a key are never split across pages
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeQuery {
+    #[serde(default)]
+    pub start: Option<DataType>,
+    #[serde(default)]
+    pub end: Option<DataType>,
+    #[serde(default)]
+    pub after: Option<DataType>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default = "return_false")]
+    pub reverse: bool,
+}
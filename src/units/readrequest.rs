@@ -0,0 +1,45 @@
+use crate::types::datatype::DataType;
+use crate::units::rangequery::RangeQuery;
+use crate::units::row::Row;
+use crate::operators::leaf::PRIMARY_INDEX;
+
+fn return_primary() -> String {
+    PRIMARY_INDEX.to_owned()
+}
+
+//ReadRequest, lookup on a server Leaf named by its path
+//keys are point lookups in index, range reads an ordered index instead when given
+//request_id is chosen by the client and echoed back in the ReadResponse
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadRequest {
+    pub request_id: u64,
+    pub leaf: String,
+    #[serde(default = "return_primary")]
+    pub index: String,
+    #[serde(default)]
+    pub keys: Vec<DataType>,
+    #[serde(default)]
+    pub range: Option<RangeQuery>,
+}
+
+//ReadResponse, rows for a ReadRequest, error set and rows empty if it failed
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadResponse {
+    pub request_id: u64,
+    pub rows: Vec<Row>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+//ReadResponse functions
+impl ReadResponse {
+    //constructor
+    pub fn new(request_id: u64, result: Result<Vec<Row>, String>) -> ReadResponse {
+        match result {
+            Ok(rows) => ReadResponse { request_id, rows, error: None },
+            Err(error) => ReadResponse { request_id, rows: Vec::new(), error: Some(error) },
+        }
+    }
+}
@@ -2,8 +2,9 @@ use std::fmt;
 use crate::types::datatype::DataType;
 
 //Row, allows 2d representation in tables 
+//ordered column by column using the DataType order
 #[derive(Debug)]
-#[derive(Hash, Eq, PartialEq, Clone)]
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Clone)]
 #[derive(Serialize, Deserialize)]
 pub struct Row {
     pub data: Vec<DataType>
@@ -0,0 +1,35 @@
+use crate::types::datatype::DataType;
+use crate::units::change::Change;
+use crate::units::row::Row;
+
+//RowFilter, a Leaf's RowPolicies worked out for one user
+//conditions: (column, value) pairs a row has to match all of to be seen
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowFilter {
+    pub(crate) conditions: Vec<(usize, DataType)>,
+}
+
+//RowFilter functions
+impl RowFilter {
+    //constructor
+    pub fn new(conditions: Vec<(usize, DataType)>) -> RowFilter {
+        RowFilter { conditions }
+    }
+
+    /// Returns whether the row can be seen
+    pub fn allows(&self, row: &Row) -> bool {
+        self.conditions.iter().all(|(col, value)| row.data.get(*col).and_then(|datum| datum.sql_eq(value)) == Some(true))
+    }
+
+    /// Keeps only the rows that can be seen
+    pub fn filter_rows(&self, rows: Vec<Row>) -> Vec<Row> {
+        rows.into_iter().filter(|row| self.allows(row)).collect()
+    }
+
+    /// Keeps only the rows that can be seen, Changes left empty stay in place
+    pub fn filter_changes(&self, change_vec: &[Change]) -> Vec<Change> {
+        change_vec.iter()
+            .map(|change| Change::new(change.typing.clone(), change.batch.iter().filter(|row| self.allows(row)).cloned().collect()))
+            .collect()
+    }
+}
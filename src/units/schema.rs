@@ -0,0 +1,40 @@
+use crate::types::schematype::SchemaType;
+
+//Schema, column names and their SchemaTypes for the rows a node outputs
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    pub column_names: Vec<String>,
+    pub types: Vec<SchemaType>,
+}
+
+//Schema functions
+impl Schema {
+    //constructor
+    pub fn new(column_names: Vec<String>, types: Vec<SchemaType>) -> Schema {
+        Schema { column_names, types }
+    }
+
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    /// Returns a single column's name and type, errors if the index is out of bounds
+    pub fn column(&self, index: usize) -> Result<(String, SchemaType), String> {
+        match self.types.get(index) {
+            Some(schema_type) => Ok((self.column_names[index].clone(), schema_type.clone())),
+            None => Err(format!("column {} out of bounds for {} columns {:?}", 
+                index, self.len(), self.column_names)),
+        }
+    }
+
+    /// Appends a column to the end of the Schema
+    pub fn push(&mut self, name: String, schema_type: SchemaType) {
+        self.column_names.push(name);
+        self.types.push(schema_type);
+    }
+}
@@ -2,17 +2,59 @@ use crate::types::changetype::ChangeType;
 use crate::units::change::Change;
 
 //Change, typing shows ChangeType, batch holds multiple potential changes
+//subscription_id tags changes sent to a subscriber with the subscription they belong to
+//request_id is chosen by a writer that wants a WriteAck back, echoed in the ack
+//client_id and write_id together name a write, a retry with the same pair is only applied once
+//seq is the Leaf's sequence number for the change, epoch the run of the Leaf it counts within
+//snapshot marks a whole table replacing the client's copy
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct ServerChange {
     pub root_id: String,
     pub changes: Vec<Change>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscription_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub snapshot: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
 }
 
 //Change functions
 impl ServerChange {
     //constructor
     pub fn new(root_id: String, changes: Vec<Change>) -> ServerChange {
-        ServerChange { root_id, changes }
+        ServerChange { root_id, changes, request_id: None, client_id: None, write_id: None, subscription_id: None, epoch: None, seq: None, snapshot: false }
+    }
+
+    /// Returns the ServerChange tagged with a subscription id
+    pub fn for_subscription(mut self, subscription_id: u64) -> ServerChange {
+        self.subscription_id = Some(subscription_id);
+        self
+    }
+
+    /// Returns the ServerChange stamped with the Leaf epoch and the sequence number it brings the client up to
+    pub fn with_seq(mut self, epoch: &str, seq: u64) -> ServerChange {
+        self.epoch = Some(epoch.to_owned());
+        self.seq = Some(seq);
+        self
+    }
+
+    /// Returns the ServerChange marked as a full snapshot of the subscribed rows
+    pub fn as_snapshot(mut self) -> ServerChange {
+        self.snapshot = true;
+        self
     }
 }
\ No newline at end of file
@@ -0,0 +1,15 @@
+//ServerError, sent back to a writer when its ServerChange is rejected
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerError {
+    pub root_id: String,
+    pub error: String,
+}
+
+//ServerError functions
+impl ServerError {
+    //constructor
+    pub fn new(root_id: String, error: String) -> ServerError {
+        ServerError { root_id, error }
+    }
+}
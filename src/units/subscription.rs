@@ -0,0 +1,83 @@
+use crate::types::datatype::DataType;
+
+//SubscribeRequest, starts listening to a server Leaf named by its path on an open connection
+//keys limits it to rows with those keys, left out is the whole Leaf
+//since and epoch are the last seq the client saw and its epoch, sent together,
+//it gets only the changes after it if the Leaf still has them
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscribeRequest {
+    pub request_id: u64,
+    pub leaf: String,
+    #[serde(default)]
+    pub keys: Option<Vec<DataType>>,
+    #[serde(default)]
+    pub since: Option<u64>,
+    #[serde(default)]
+    pub epoch: Option<String>,
+}
+
+//Resume, where a reconnecting client left off
+//epoch names the run of the Leaf seq counts within, a Leaf gets a new one every time the server starts,
+//so a seq from before a restart is never mistaken for one from after it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resume {
+    pub epoch: String,
+    pub seq: u64,
+}
+
+//Resume functions
+impl Resume {
+    /// Pairs up a since and an epoch, which have to be sent together, None if neither was
+    pub fn from_parts(since: Option<u64>, epoch: Option<String>) -> Result<Option<Resume>, String> {
+        match (since, epoch) {
+            (Some(seq), Some(epoch)) => Ok(Some(Resume { epoch, seq })),
+            (None, None) => Ok(None),
+            _ => Err("since and epoch have to be sent together".to_owned()),
+        }
+    }
+
+    /// Reads ?since=N&epoch=E from query parameters
+    pub fn from_query(params: &[(String, String)]) -> Result<Option<Resume>, String> {
+        let param = |name: &str| params.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone());
+        let since = match param("since") {
+            Some(text) => Some(text.parse::<u64>().map_err(|_| format!("bad since {:?}", text))?),
+            None => None,
+        };
+
+        Resume::from_parts(since, param("epoch"))
+    }
+}
+
+//UnsubscribeRequest, stops a subscription made earlier on the same connection
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsubscribeRequest {
+    pub request_id: u64,
+    pub subscription_id: u64,
+}
+
+//the request_id of the SubscribeResponse for the subscription a reader's path makes when it connects
+pub const PATH_SUBSCRIPTION_REQUEST_ID: u64 = 0;
+
+//SubscribeResponse, answer to either request
+//subscription_id tags every ServerChange sent for the subscription, error set if it failed
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscribeResponse {
+    pub request_id: u64,
+    pub subscription_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+//SubscribeResponse functions
+impl SubscribeResponse {
+    //constructor
+    pub fn new(request_id: u64, result: Result<u64, String>) -> SubscribeResponse {
+        match result {
+            Ok(id) => SubscribeResponse { request_id, subscription_id: Some(id), error: None },
+            Err(error) => SubscribeResponse { request_id, subscription_id: None, error: Some(error) },
+        }
+    }
+}
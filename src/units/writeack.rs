@@ -0,0 +1,18 @@
+//WriteAck, answer to a Write that carried a request_id, sent once change_to_root is done
+//error set if the write was rejected, nothing from it was applied then
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteAck {
+    pub request_id: u64,
+    pub root_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+//WriteAck functions
+impl WriteAck {
+    //constructor
+    pub fn new(request_id: u64, root_id: String, result: Result<(), String>) -> WriteAck {
+        WriteAck { request_id, root_id, error: result.err() }
+    }
+}
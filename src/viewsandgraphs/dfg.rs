@@ -4,14 +4,28 @@ use wasm_bindgen::prelude::*;
 use std::cell::{RefCell};
 use petgraph::graph::Graph;
 use serde_json::Value;
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+use std::sync::Arc;
+use std::collections::HashSet;
 
 use petgraph::graph::NodeIndex;
+use petgraph::Direction;
+use petgraph::algo::toposort;
 use crate::operators::Operator;
 use crate::units::row::Row;
 use crate::units::change::Change;
+use crate::units::schema::Schema;
+use crate::units::rangequery::RangeQuery;
+use crate::units::readrequest::{ReadRequest, ReadResponse};
+use crate::units::serverchange::ServerChange;
+use crate::units::subscription::Resume;
+use crate::units::rowfilter::RowFilter;
+use crate::subscribers::Subscriber;
+use crate::viewsandgraphs::writededup::{Claim, WriteDedup, WriteKey, DEFAULT_DEDUP_WINDOW};
 use crate::types::datatype::DataType;
 use crate::types::changetype::ChangeType;
+use crate::types::rowpolicy::RowPolicy;
 use crate::types::operatortype::OperatorType;
 use crate::types::permissiontype::PermissionType;
 use crate::operators::operation::Operation;
@@ -33,12 +47,16 @@ use crate::operators::projection::Projection;
 //DataFlowGraph
 //root_id_map: map of root_id's to their NodeIndexes
 //leaf_id_vec: just a list of leaf ids, used for printing
+//schemas: output Schema of every node, filled by build_schemas once the graph is built
+//dedup: outcomes of recent writes that named themselves with a client_id and write_id
 #[derive(Debug)]
 pub struct DataFlowGraph {
     pub(crate) data: Graph<RwLock<Operation>, ()>,
     root_id_map: HashMap<String, NodeIndex>,
     leaf_id_vec: Vec<NodeIndex>,
     pub(crate) path_leaf_map: HashMap<String, NodeIndex>,
+    schemas: HashMap<NodeIndex, Schema>,
+    dedup: Mutex<WriteDedup>,
 }
 
 //Displays DFG
@@ -48,7 +66,7 @@ impl fmt::Display for DataFlowGraph {
             let op_ref = self.data.node_weight(leaf_index).unwrap().write().unwrap();
 
             match &*op_ref {
-                Operation::Leafor(leaf) => write!(f, "{:#?}", leaf.table),
+                Operation::Leafor(leaf) => write!(f, "{:#?}", leaf.table.map),
                 _ => Ok(())
             };
         }
@@ -64,27 +82,64 @@ impl DataFlowGraph {
         let mut root_id_map = HashMap::new();
         let mut leaf_id_vec = Vec::new();
         let mut path_leaf_map = HashMap::new(); 
-  
+        let schemas = HashMap::new();
+        let dedup = Mutex::new(WriteDedup::new(DEFAULT_DEDUP_WINDOW));
 
-        DataFlowGraph { data, root_id_map, leaf_id_vec, path_leaf_map }
+        DataFlowGraph { data, root_id_map, leaf_id_vec, path_leaf_map, schemas, dedup }
     }
 
-    pub fn change_to_root_json(&self, root_string: String, row_chng_json: String) {
-        let change: Change = serde_json::from_str(&row_chng_json).unwrap();
+    pub fn change_to_root_json(&self, root_string: String, row_chng_json: String) -> Result<(), String> {
+        let change: Change = serde_json::from_str(&row_chng_json).map_err(|err| err.to_string())?;
 
-        let root_node_index = *(self.root_id_map.get(&root_string).unwrap());
-        let mut root_op = self.data.node_weight(root_node_index.clone()).unwrap().write().unwrap();
+        self.change_to_root(root_string, vec![change])
+    }
+
+    /// Validates a set of Changes against the Root's schema before sending them through the graph,
+    /// a bad batch is rejected whole
+    pub fn change_to_root(&self, root_string: String, chng_vec: Vec<Change>) -> Result<(), String> {
+        let root_node_index = match self.root_id_map.get(&root_string) {
+            Some(index) => *index,
+            None => return Err(format!("no root with id {}", root_string)),
+        };
+        let mut root_op = self.data.node_weight(root_node_index).unwrap().write().unwrap();
 
-        let change_vec = vec![change];
+        if let Rootor(root) = &*root_op {
+            root.check_changes(&chng_vec)?;
+        }
 
-        root_op.process_change(change_vec, self, NodeIndex::new(1), root_node_index.clone());
+        root_op.process_change(chng_vec, self, NodeIndex::new(1), root_node_index);
+
+        Ok(())
     }
 
-    pub fn change_to_root(&self, root_string: String, chng_vec: Vec<Change>) {
-        let root_node_index = *(self.root_id_map.get(&root_string).unwrap());
-        let mut root_op = self.data.node_weight(root_node_index.clone()).unwrap().write().unwrap();
+    /// Applies a client's ServerChange, a write with a client_id and write_id already seen
+    /// within the dedup window isn't applied again, the first outcome is returned instead
+    /// principal is the authenticated user_id making the write, None if auth is off
+    pub fn write(&self, sc: ServerChange, principal: Option<&str>) -> Result<(), String> {
+        let (client_id, write_id) = match (sc.client_id, sc.write_id) {
+            (Some(client_id), Some(write_id)) => (client_id, write_id),
+            (None, None) => return self.change_to_root(sc.root_id, sc.changes),
+            _ => return Err("client_id and write_id have to be sent together".to_owned()),
+        };
+        let key = WriteKey { principal: principal.map(str::to_owned), root_id: sc.root_id.clone(), client_id, write_id };
+
+        //the map lock is only held to claim the ids, a retry racing the original waits on its outcome
+        let claim = self.dedup.lock().unwrap().claim(key, &sc.changes)?;
+
+        match claim {
+            Claim::Apply(applying) => {
+                let result = self.change_to_root(sc.root_id, sc.changes);
+                applying.finish(result.clone());
+
+                result
+            },
+            Claim::Seen(outcome) => outcome.wait(),
+        }
+    }
 
-        root_op.process_change(chng_vec, self, NodeIndex::new(1), root_node_index.clone());
+    /// Sets how long client write ids are remembered for deduplication
+    pub fn set_dedup_window(&self, window: Duration) {
+        self.dedup.lock().unwrap().set_window(window);
     }
 
     pub fn add_node(&mut self, op_type: OperatorType, json: String) {
@@ -116,8 +171,9 @@ impl DataFlowGraph {
         }
     }
 
-    pub fn add_leaf(&mut self, root_pair_id: String, key_index: usize, path: String) {
-        let leaf = Leaf::new(root_pair_id, key_index);
+    /// multi_row Leafs keep every row for a key instead of only the latest
+    pub fn add_leaf(&mut self, root_pair_id: String, key_index: usize, multi_row: bool, path: String) {
+        let leaf = Leaf::new(root_pair_id, key_index, multi_row);
         let index = self.data.add_node(RwLock::new(Leafor(leaf)));
         self.path_leaf_map.insert(path, index);
         self.leaf_id_vec.push(index); 
@@ -129,21 +185,249 @@ impl DataFlowGraph {
         self.data.add_edge(pni, cni, {});
     }
 
-    pub fn read(&self, leaf_index: usize, key_string: String) -> String {
+    /// Computes the output Schema of every node from the Root schemas down, validating operator
+    /// configs along the way, should be called after all nodes and edges are added
+    pub fn build_schemas(&mut self) -> Result<(), String> {
+        let order = toposort(&self.data, None).map_err(|_cycle| "graph has a cycle".to_owned())?;
+        let mut schemas: HashMap<NodeIndex, Schema> = HashMap::new();
+
+        for index in order {
+            let schema = {
+                let parents: Vec<(usize, &Schema)> = self.data.neighbors_directed(index, Direction::Incoming)
+                    .map(|parent_index| (parent_index.index(), &schemas[&parent_index]))
+                    .collect();
+                let op = self.data.node_weight(index).unwrap().read().unwrap();
+
+                op.output_schema(&parents).map_err(|err| format!("node {}: {}", index.index(), err))?
+            };
+
+            schemas.insert(index, schema);
+        }
+
+        self.schemas = schemas;
+
+        Ok(())
+    }
+
+    /// Returns the Schema of a node, None before build_schemas
+    pub fn schema(&self, index: usize) -> Option<&Schema> {
+        self.schemas.get(&NodeIndex::new(index))
+    }
+
+    /// Returns the Schema of the Leaf whose root_pair_id matches, the client Root it feeds
+    pub fn leaf_schema(&self, root_pair_id: &str) -> Option<&Schema> {
+        for index in &self.leaf_id_vec {
+            let leaf_ref = self.data.node_weight(*index).unwrap().read().unwrap();
+
+            if let Leafor(leaf) = &*leaf_ref {
+                if leaf.root_pair_id == root_pair_id {
+                    return self.schemas.get(index);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns the NodeIndex of the Leaf serving a path
+    pub fn leaf_index(&self, path: &str) -> Option<usize> {
+        self.path_leaf_map.get(path).map(|index| index.index())
+    }
+
+    pub fn has_root(&self, root_id: &str) -> bool {
+        self.root_id_map.contains_key(root_id)
+    }
+
+    /// Parses plain text as a key for a Leaf's index, using the type of the column it's keyed on
+    pub fn parse_key(&self, leaf_index: usize, index_name: &str, text: &str) -> Result<DataType, String> {
+        let col = {
+            let leaf_op = match self.data.node_weight(NodeIndex::new(leaf_index)) {
+                Some(op) => op.read().unwrap(),
+                None => return Err(format!("no node {}", leaf_index)),
+            };
+
+            match &*leaf_op {
+                Leafor(leaf) => leaf.index_col(index_name).ok_or(format!("no index {}", index_name))?,
+                _ => return Err(format!("node {} is not a leaf", leaf_index)),
+            }
+        };
+
+        match self.schema(leaf_index) {
+            Some(schema) => schema.column(col)?.1.parse(text),
+            None => Err("schemas have not been built".to_owned()),
+        }
+    }
+
+    /// Adds a RowPolicy to the Leaf at leaf_index
+    pub fn add_row_policy(&mut self, leaf_index: usize, policy: RowPolicy) {
+        let mut leaf_op = self.data.node_weight(NodeIndex::new(leaf_index)).unwrap().write().unwrap();
+
+        if let Leafor(leaf) = &mut *leaf_op {
+            leaf.add_policy(policy);
+        }
+    }
+
+    /// Works out the Leaf's RowPolicies for a user, None if the Leaf has none,
+    /// a Leaf with policies can't be read without a user
+    pub fn row_filter(&self, leaf_index: usize, user_id: Option<&str>) -> Result<Option<RowFilter>, String> {
+        let policies = {
+            let leaf_op = match self.data.node_weight(NodeIndex::new(leaf_index)) {
+                Some(op) => op.read().unwrap(),
+                None => return Err(format!("no node {}", leaf_index)),
+            };
+
+            match &*leaf_op {
+                Leafor(leaf) => leaf.policies.clone(),
+                _ => return Err(format!("node {} is not a leaf", leaf_index)),
+            }
+        };
+
+        if policies.is_empty() {
+            return Ok(None);
+        }
+
+        let user_id = user_id.ok_or(format!("leaf {} needs an authenticated user", leaf_index))?;
+        let schema = self.schema(leaf_index).ok_or("schemas have not been built".to_owned())?;
+        let mut conditions = Vec::new();
+
+        for policy in policies {
+            match policy {
+                RowPolicy::ColumnEqualsUser(col) => conditions.push((col, schema.column(col)?.1.parse(user_id)?)),
+            }
+        }
+
+        Ok(Some(RowFilter::new(conditions)))
+    }
+
+    /// Subscribes a Subscriber to the Leaf at leaf_index, see Leaf::subscribe
+    /// user_id is who's subscribing, the Leaf's RowPolicies are applied for them
+    pub fn subscribe(&self, leaf_index: usize, id: u64, subscriber: Arc<dyn Subscriber>, 
+        keys: Option<HashSet<DataType>>, since: Option<Resume>, user_id: Option<&str>) -> Result<(), String> {
+        let filter = self.row_filter(leaf_index, user_id)?;
+
+        let mut leaf_op = match self.data.node_weight(NodeIndex::new(leaf_index)) {
+            Some(op) => op.write().unwrap(),
+            None => return Err(format!("no node {}", leaf_index)),
+        };
+
+        match &mut *leaf_op {
+            Leafor(leaf) => {
+                leaf.subscribe(id, subscriber, keys, filter, since);
+                Ok(())
+            },
+            _ => Err(format!("node {} is not a leaf", leaf_index)),
+        }
+    }
+
+    /// Removes a subscription from the Leaf at leaf_index, returns whether it was there
+    pub fn unsubscribe(&self, leaf_index: usize, id: u64) -> bool {
+        match self.data.node_weight(NodeIndex::new(leaf_index)) {
+            Some(op) => match &mut *op.write().unwrap() {
+                Leafor(leaf) => leaf.unsubscribe(id),
+                _ => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Adds a secondary index to the Leaf at leaf_index, ordered indexes support range reads
+    pub fn add_index(&mut self, leaf_index: usize, name: String, col: usize, ordered: bool) {
         let mut leaf_op = self.data.node_weight(NodeIndex::new(leaf_index)).unwrap().write().unwrap();
-        let key: DataType = serde_json::from_str(&key_string).unwrap();
+
+        if let Leafor(leaf) = &mut *leaf_op {
+            leaf.add_index(name, col, ordered);
+        }
+    }
+
+    /// Returns every row matching key in the Leaf's named index, PRIMARY_INDEX for the table
+    /// user_id is who's reading, rows the Leaf's RowPolicies hide from them are left out
+    pub fn lookup(&self, leaf_index: usize, index_name: &str, key: &DataType, user_id: Option<&str>) -> Result<Vec<Row>, String> {
+        let filter = self.row_filter(leaf_index, user_id)?;
+
+        let leaf_op = match self.data.node_weight(NodeIndex::new(leaf_index)) {
+            Some(op) => op.read().unwrap(),
+            None => return Err(format!("no node {}", leaf_index)),
+        };
 
         match &*leaf_op {
             Leafor(leaf) => {
-                let row = (*leaf).table.get(&key).unwrap();
-                let j = serde_json::to_string(&row);
-                
-                match j {
-                    Ok(string) => string,
-                    Err(_err) => "error".to_owned(),
+                let rows = leaf.lookup(index_name, key)?;
+
+                match filter {
+                    Some(filter) => Ok(filter.filter_rows(rows)),
+                    None => Ok(rows),
                 }
-            }
-            _ => "error".to_owned(),
+            },
+            _ => Err(format!("node {} is not a leaf", leaf_index)),
+        }
+    }
+
+    /// Returns rows from the Leaf's named ordered index in key order, see RangeQuery
+    /// user_id is who's reading, rows the Leaf's RowPolicies hide from them are left out
+    pub fn range(&self, leaf_index: usize, index_name: &str, query: &RangeQuery, user_id: Option<&str>) -> Result<Vec<Row>, String> {
+        let filter = self.row_filter(leaf_index, user_id)?;
+
+        let leaf_op = match self.data.node_weight(NodeIndex::new(leaf_index)) {
+            Some(op) => op.read().unwrap(),
+            None => return Err(format!("no node {}", leaf_index)),
+        };
+
+        match &*leaf_op {
+            Leafor(leaf) => leaf.range(index_name, query, filter.as_ref()),
+            _ => Err(format!("node {} is not a leaf", leaf_index)),
+        }
+    }
+
+    /// JSON version of range, takes a RangeQuery and returns the rows as a JSON array
+    pub fn read_range(&self, leaf_index: usize, index_name: String, query_string: String) -> String {
+        let query: RangeQuery = match serde_json::from_str(&query_string) {
+            Ok(query) => query,
+            Err(_err) => return "error".to_owned(),
+        };
+
+        match self.range(leaf_index, &index_name, &query, None) {
+            Ok(rows) => serde_json::to_string(&rows).unwrap(),
+            Err(_err) => "error".to_owned(),
+        }
+    }
+
+    /// Answers a ReadRequest, a range read if it has one, otherwise a lookup of every key
+    /// user_id is who's reading, rows their RowPolicies hide are left out
+    pub fn read_request(&self, request: &ReadRequest, user_id: Option<&str>) -> ReadResponse {
+        let leaf_index = match self.path_leaf_map.get(&request.leaf) {
+            Some(index) => index.index(),
+            None => return ReadResponse::new(request.request_id, Err(format!("no leaf at {}", request.leaf))),
+        };
+
+        let result = match &request.range {
+            Some(query) => self.range(leaf_index, &request.index, query, user_id),
+            None => {
+                let mut rows = Vec::new();
+
+                for key in &request.keys {
+                    match self.lookup(leaf_index, &request.index, key, user_id) {
+                        Ok(found) => rows.extend(found),
+                        Err(err) => return ReadResponse::new(request.request_id, Err(err)),
+                    }
+                }
+
+                Ok(rows)
+            },
+        };
+
+        ReadResponse::new(request.request_id, result)
+    }
+
+    /// JSON version of lookup, returns the matching rows as a JSON array
+    pub fn read(&self, leaf_index: usize, index_name: String, key_string: String) -> String {
+        let key: DataType = match serde_json::from_str(&key_string) {
+            Ok(key) => key,
+            Err(_err) => return "error".to_owned(),
+        };
+
+        match self.lookup(leaf_index, &index_name, &key, None) {
+            Ok(rows) => serde_json::to_string(&rows).unwrap(),
+            Err(_err) => "error".to_owned(),
         }
     }
 
@@ -173,4 +457,171 @@ impl DataFlowGraph {
 
         node_vec
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::schematype::SchemaType;
+    use crate::operators::leaf::PRIMARY_INDEX;
+    use crate::subscribers::inprocess::InProcessSubscriber;
+
+    const STORIES: &str = r#"{
+        "root_id": "Stories",
+        "key_index": 0,
+        "column_names": ["StoryID", "AuthorID", "Title"],
+        "schema": ["Int", "Int", "Text"]
+    }"#;
+    const USERS: &str = r#"{
+        "root_id": "Users",
+        "key_index": 0,
+        "column_names": ["UserID", "Name"],
+        "schema": ["Int", "Text"]
+    }"#;
+
+    //Stories at node 0 and Users at node 1
+    fn roots() -> DataFlowGraph {
+        let mut graph = DataFlowGraph::new();
+        graph.add_node(OperatorType::R, STORIES.to_owned());
+        graph.add_node(OperatorType::R, USERS.to_owned());
+
+        graph
+    }
+
+    fn join(join_cols: &str) -> String {
+        format!(r#"{{"parent_ids": [0, 1], "join_cols": {}}}"#, join_cols)
+    }
+
+    #[test]
+    fn schemas_flow_through_joins_and_projections() {
+        let mut graph = roots();
+        graph.add_node(OperatorType::I, join("[1, 0]"));
+        graph.add_node(OperatorType::P, r#"{"columns": [1, 3]}"#.to_owned());
+        graph.add_leaf("Feed".to_owned(), 0, false, "/feed".to_owned());
+        graph.add_edge(0, 2);
+        graph.add_edge(1, 2);
+        graph.add_edge(2, 3);
+        graph.add_edge(3, 4);
+        graph.build_schemas().unwrap();
+
+        //the left join column goes, the right one stays
+        let joined = graph.schema(2).unwrap();
+        assert_eq!(joined.column_names, vec!["StoryID", "Title", "UserID", "Name"]);
+        assert_eq!(joined.types, vec![SchemaType::Int, SchemaType::Text, SchemaType::Int, SchemaType::Text]);
+
+        let feed = Schema::new(vec!["Title".to_owned(), "Name".to_owned()], vec![SchemaType::Text, SchemaType::Text]);
+        assert_eq!(graph.schema(3), Some(&feed));
+        assert_eq!(graph.leaf_schema("Feed"), Some(&feed));
+        assert_eq!(graph.leaf_schema("Nothing"), None);
+    }
+
+    #[test]
+    fn join_columns_of_different_types_are_an_error() {
+        let mut graph = roots();
+        graph.add_node(OperatorType::I, join("[2, 0]"));
+        graph.add_edge(0, 2);
+        graph.add_edge(1, 2);
+
+        let err = graph.build_schemas().unwrap_err();
+        assert!(err.starts_with("node 2: join: column Title"), "{}", err);
+    }
+
+    #[test]
+    fn selection_condition_has_to_match_its_column() {
+        let mut graph = roots();
+        graph.add_node(OperatorType::S, r#"{"col_ind": 0, "condition": {"t": "Text", "c": "1"}}"#.to_owned());
+        graph.add_edge(0, 2);
+
+        let err = graph.build_schemas().unwrap_err();
+        assert!(err.contains("condition Text(\"1\") does not match column StoryID"), "{}", err);
+    }
+
+    #[test]
+    fn missing_parents_and_join_cols_are_errors() {
+        let mut graph = roots();
+        graph.add_node(OperatorType::P, r#"{"columns": [0]}"#.to_owned());
+        assert_eq!(graph.build_schemas().unwrap_err(), "node 2: expected exactly one parent, found 0");
+
+        let mut graph = roots();
+        graph.add_node(OperatorType::I, join("[1, 0]"));
+        graph.add_edge(0, 2);
+        assert_eq!(graph.build_schemas().unwrap_err(), "node 2: join: parent 1 is not connected");
+
+        let mut graph = roots();
+        graph.add_node(OperatorType::I, join("[1]"));
+        graph.add_edge(0, 2);
+        graph.add_edge(1, 2);
+        assert_eq!(graph.build_schemas().unwrap_err(), "node 2: join: expects two join_cols");
+
+        let mut graph = roots();
+        graph.add_node(OperatorType::P, r#"{"columns": [5]}"#.to_owned());
+        graph.add_edge(1, 2);
+        assert!(graph.build_schemas().unwrap_err().starts_with("node 2: projection: column 5 out of bounds"));
+    }
+
+    fn story(id: i32, author: i32) -> Row {
+        Row::new(vec![DataType::Int(id), DataType::Int(author), DataType::Text(format!("story {}", id))])
+    }
+
+    fn insert(graph: &DataFlowGraph, rows: Vec<Row>) {
+        graph.change_to_root("Stories".to_owned(), vec![Change::new(ChangeType::Insertion, rows)]).unwrap();
+    }
+
+    fn sorted(mut rows: Vec<Row>) -> Vec<Row> {
+        rows.sort_by_key(|row| row.data[0].clone());
+        rows
+    }
+
+    //Stories with a Leaf at node 1 that only shows authors their own stories
+    fn private_stories() -> DataFlowGraph {
+        let mut graph = DataFlowGraph::new();
+        graph.add_node(OperatorType::R, STORIES.to_owned());
+        graph.add_leaf("Stories".to_owned(), 0, false, "/stories".to_owned());
+        graph.add_edge(0, 1);
+        graph.add_row_policy(1, RowPolicy::ColumnEqualsUser(1));
+        graph.build_schemas().unwrap();
+
+        insert(&graph, vec![story(1, 10), story(2, 20), story(3, 10)]);
+
+        graph
+    }
+
+    #[test]
+    fn lookups_are_filtered_per_user() {
+        let graph = private_stories();
+
+        assert_eq!(graph.lookup(1, PRIMARY_INDEX, &DataType::Int(1), Some("10")).unwrap(), vec![story(1, 10)]);
+        assert!(graph.lookup(1, PRIMARY_INDEX, &DataType::Int(1), Some("20")).unwrap().is_empty());
+        assert!(graph.lookup(1, PRIMARY_INDEX, &DataType::Int(1), None).is_err());
+
+        let request = ReadRequest { request_id: 1, leaf: "/stories".to_owned(), index: PRIMARY_INDEX.to_owned(),
+            keys: vec![DataType::Int(1), DataType::Int(2), DataType::Int(3)], range: None };
+        assert_eq!(graph.read_request(&request, Some("10")).rows, vec![story(1, 10), story(3, 10)]);
+        assert_eq!(graph.read_request(&request, Some("20")).rows, vec![story(2, 20)]);
+    }
+
+    #[test]
+    fn snapshots_and_deltas_are_filtered_per_user() {
+        let graph = private_stories();
+        let (alice, alice_changes) = InProcessSubscriber::new();
+        let (bob, bob_changes) = InProcessSubscriber::new();
+        graph.subscribe(1, 1, Arc::new(alice), None, None, Some("10")).unwrap();
+        graph.subscribe(1, 2, Arc::new(bob), None, None, Some("20")).unwrap();
+
+        let rows = |change: ServerChange| -> Vec<Row> { change.changes.into_iter().flat_map(|change| change.batch).collect() };
+        assert_eq!(sorted(rows(alice_changes.try_recv().unwrap())), vec![story(1, 10), story(3, 10)]);
+        assert_eq!(rows(bob_changes.try_recv().unwrap()), vec![story(2, 20)]);
+
+        insert(&graph, vec![story(4, 20), story(5, 10)]);
+        assert_eq!(rows(alice_changes.try_recv().unwrap()), vec![story(5, 10)]);
+        assert_eq!(rows(bob_changes.try_recv().unwrap()), vec![story(4, 20)]);
+
+        //a change with nothing for a user isn't sent to them at all
+        insert(&graph, vec![story(6, 20)]);
+        assert!(alice_changes.try_recv().is_err());
+        assert_eq!(rows(bob_changes.try_recv().unwrap()), vec![story(6, 20)]);
+
+        let (anonymous, _) = InProcessSubscriber::new();
+        assert!(graph.subscribe(1, 3, Arc::new(anonymous), None, None, None).is_err());
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+
+use crate::types::datatype::DataType;
+use crate::units::row::Row;
+use crate::units::rangequery::RangeQuery;
+use crate::units::rowfilter::RowFilter;
+
+//IndexMap, rows by key, Ordered indexes can also be read by key range
+#[derive(Debug, Clone)]
+pub enum IndexMap {
+    Hashed(HashMap<DataType, Vec<Row>>),
+    Ordered(BTreeMap<DataType, Vec<Row>>),
+}
+
+//IndexMap functions, the parts of the map API Index needs
+impl IndexMap {
+    fn get(&self, key: &DataType) -> Option<&Vec<Row>> {
+        match self {
+            IndexMap::Hashed(map) => map.get(key),
+            IndexMap::Ordered(map) => map.get(key),
+        }
+    }
+
+    fn get_mut(&mut self, key: &DataType) -> Option<&mut Vec<Row>> {
+        match self {
+            IndexMap::Hashed(map) => map.get_mut(key),
+            IndexMap::Ordered(map) => map.get_mut(key),
+        }
+    }
+
+    fn insert(&mut self, key: DataType, rows: Vec<Row>) -> Option<Vec<Row>> {
+        match self {
+            IndexMap::Hashed(map) => map.insert(key, rows),
+            IndexMap::Ordered(map) => map.insert(key, rows),
+        }
+    }
+
+    fn remove(&mut self, key: &DataType) -> Option<Vec<Row>> {
+        match self {
+            IndexMap::Hashed(map) => map.remove(key),
+            IndexMap::Ordered(map) => map.remove(key),
+        }
+    }
+
+    fn values(&self) -> Box<dyn Iterator<Item = &Vec<Row>> + '_> {
+        match self {
+            IndexMap::Hashed(map) => Box::new(map.values()),
+            IndexMap::Ordered(map) => Box::new(map.values()),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            IndexMap::Hashed(map) => map.len(),
+            IndexMap::Ordered(map) => map.len(),
+        }
+    }
+}
+
+//Index, lookup on a Leaf's rows
+//col: column the index is keyed on, map holds every row with that value
+//unique: a row replaces any other with the same key, used for single row views
+#[derive(Debug, Clone)]
+pub struct Index {
+    pub(crate) col: usize,
+    pub(crate) unique: bool,
+    pub(crate) map: IndexMap,
+}
+
+//Index functions
+impl Index {
+    //constructor, ordered indexes are BTreeMap backed and support range reads
+    pub fn new(col: usize, unique: bool, ordered: bool) -> Index {
+        let map = if ordered {
+            IndexMap::Ordered(BTreeMap::new())
+        } else {
+            IndexMap::Hashed(HashMap::new())
+        };
+
+        Index { col, unique, map }
+    }
+
+    /// Adds a row, returns the rows it displaced if the index is unique
+    pub fn insert(&mut self, row: &Row) -> Vec<Row> {
+        let key = row.data[self.col].clone();
+
+        if self.unique {
+            return self.map.insert(key, vec![row.clone()]).unwrap_or_default();
+        }
+
+        match self.map.get_mut(&key) {
+            Some(rows) => rows.push(row.clone()),
+            None => {
+                self.map.insert(key, vec![row.clone()]);
+            },
+        }
+
+        Vec::new()
+    }
+
+    /// Removes a row and returns what was removed, a unique index drops whatever holds the key,
+    /// otherwise only a single exactly matching row goes and others with the same key stay
+    pub fn remove(&mut self, row: &Row) -> Vec<Row> {
+        let key = &row.data[self.col];
+
+        if self.unique {
+            return self.map.remove(key).unwrap_or_default();
+        }
+
+        let mut removed = Vec::new();
+
+        if let Some(rows) = self.map.get_mut(key) {
+            if let Some(pos) = rows.iter().position(|r| r == row) {
+                removed.push(rows.remove(pos));
+            }
+
+            if rows.is_empty() {
+                self.map.remove(key);
+            }
+        }
+
+        removed
+    }
+
+    /// Returns every row with the key, empty if there are none
+    pub fn get(&self, key: &DataType) -> Vec<Row> {
+        match self.map.get(key) {
+            Some(rows) => rows.clone(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns rows in key order for an ordered index, see RangeQuery
+    /// rows filter hides are skipped before the limit, so pages stay full
+    pub fn range(&self, query: &RangeQuery, filter: Option<&RowFilter>) -> Result<Vec<Row>, String> {
+        let map = match &self.map {
+            IndexMap::Ordered(map) => map,
+            IndexMap::Hashed(_) => return Err("range reads need an ordered index".to_owned()),
+        };
+
+        let mut lower = match &query.start {
+            Some(start) => Bound::Included(start),
+            None => Bound::Unbounded,
+        };
+        let mut upper = match &query.end {
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        };
+
+        //the cursor tightens whichever bound is in the direction read
+        if let Some(after) = &query.after {
+            if query.reverse {
+                upper = match upper {
+                    Bound::Excluded(end) if end < after => Bound::Excluded(end),
+                    _ => Bound::Excluded(after),
+                };
+            } else {
+                lower = match lower {
+                    Bound::Included(start) if start > after => Bound::Included(start),
+                    _ => Bound::Excluded(after),
+                };
+            }
+        }
+
+        if is_empty_range(lower, upper) {
+            return Ok(Vec::new());
+        }
+
+        let keys: Box<dyn Iterator<Item = &Vec<Row>>> = if query.reverse {
+            Box::new(map.range::<DataType, _>((lower, upper)).rev().map(|(_, rows)| rows))
+        } else {
+            Box::new(map.range::<DataType, _>((lower, upper)).map(|(_, rows)| rows))
+        };
+
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let mut result = Vec::new();
+
+        if limit == 0 {
+            return Ok(result);
+        }
+
+        for rows in keys {
+            let rows = match filter {
+                Some(filter) => filter.filter_rows(rows.clone()),
+                None => rows.clone(),
+            };
+
+            //a key's rows all go on the same page, unless it alone is over the limit
+            if !result.is_empty() && result.len() + rows.len() > limit {
+                break;
+            }
+
+            result.extend(rows);
+
+            if result.len() >= limit {
+                break;
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Returns every row in the index
+    pub fn rows(&self) -> impl Iterator<Item = &Row> {
+        self.map.values().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.values().map(|rows| rows.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.len() == 0
+    }
+}
+
+//BTreeMap::range panics on inverted bounds, those just read nothing
+fn is_empty_range(lower: Bound<&DataType>, upper: Bound<&DataType>) -> bool {
+    match (lower, upper) {
+        (Bound::Included(l), Bound::Included(u)) => l > u,
+        (Bound::Included(l), Bound::Excluded(u))
+        | (Bound::Excluded(l), Bound::Included(u))
+        | (Bound::Excluded(l), Bound::Excluded(u)) => l >= u,
+        _ => false,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(n: i32) -> DataType {
+        DataType::Int(n)
+    }
+
+    //rows (key, n), keys 1 to 5 with one row each, key 3 with three rows
+    fn index() -> Index {
+        let mut index = Index::new(0, false, true);
+
+        for key in 1..=5 {
+            index.insert(&Row::new(vec![int(key), int(0)]));
+        }
+        for n in 1..=2 {
+            index.insert(&Row::new(vec![int(3), int(n)]));
+        }
+
+        index
+    }
+
+    fn query(start: Option<i32>, end: Option<i32>, after: Option<i32>, limit: Option<usize>, reverse: bool) -> RangeQuery {
+        RangeQuery { start: start.map(int), end: end.map(int), after: after.map(int), limit, reverse }
+    }
+
+    fn keys(rows: Vec<Row>) -> Vec<DataType> {
+        rows.into_iter().map(|row| row.data[0].clone()).collect()
+    }
+
+    #[test]
+    fn reads_between_bounds_in_order() {
+        let rows = index().range(&query(Some(2), Some(5), None, None, false), None).unwrap();
+        assert_eq!(keys(rows), vec![int(2), int(3), int(3), int(3), int(4)]);
+
+        let rows = index().range(&query(Some(2), Some(5), None, None, true), None).unwrap();
+        assert_eq!(keys(rows), vec![int(4), int(3), int(3), int(3), int(2)]);
+    }
+
+    #[test]
+    fn forward_cursor_reads_past_after() {
+        let rows = index().range(&query(None, None, Some(3), Some(2), false), None).unwrap();
+        assert_eq!(keys(rows), vec![int(4), int(5)]);
+
+        //a start past the cursor wins
+        let rows = index().range(&query(Some(5), None, Some(1), None, false), None).unwrap();
+        assert_eq!(keys(rows), vec![int(5)]);
+    }
+
+    #[test]
+    fn reverse_cursor_reads_below_after() {
+        let rows = index().range(&query(None, None, Some(3), Some(2), true), None).unwrap();
+        assert_eq!(keys(rows), vec![int(2), int(1)]);
+
+        //an end below the cursor wins
+        let rows = index().range(&query(None, Some(2), Some(5), None, true), None).unwrap();
+        assert_eq!(keys(rows), vec![int(1)]);
+    }
+
+    #[test]
+    fn inverted_bounds_read_nothing() {
+        assert!(index().range(&query(Some(4), Some(2), None, None, false), None).unwrap().is_empty());
+        assert!(index().range(&query(Some(3), Some(3), None, None, false), None).unwrap().is_empty());
+        assert!(index().range(&query(None, Some(3), Some(3), None, false), None).unwrap().is_empty());
+        assert!(index().range(&query(Some(3), None, Some(3), None, true), None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn zero_limit_reads_nothing() {
+        assert!(index().range(&query(None, None, None, Some(0), false), None).unwrap().is_empty());
+        assert!(index().range(&query(Some(3), None, None, Some(0), true), None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn keys_are_never_split_across_pages() {
+        //key 3 would go over the limit, so the page stops before it
+        let rows = index().range(&query(None, None, None, Some(3), false), None).unwrap();
+        assert_eq!(keys(rows), vec![int(1), int(2)]);
+
+        //unless it comes first, then it is returned whole
+        let rows = index().range(&query(Some(3), None, None, Some(2), false), None).unwrap();
+        assert_eq!(keys(rows), vec![int(3), int(3), int(3)]);
+    }
+
+    #[test]
+    fn filtered_rows_do_not_count_toward_the_limit() {
+        let filter = RowFilter::new(vec![(1, int(0))]);
+        let rows = index().range(&query(None, None, None, Some(4), false), Some(&filter)).unwrap();
+
+        assert_eq!(keys(rows), vec![int(1), int(2), int(3), int(4)]);
+    }
+
+    #[test]
+    fn hashed_indexes_refuse_range_reads() {
+        let index = Index::new(0, false, false);
+        assert!(index.range(&query(None, None, None, None, false), None).is_err());
+    }
+
+    #[test]
+    fn remove_takes_only_the_exact_row() {
+        let mut index = index();
+        let removed = index.remove(&Row::new(vec![int(3), int(1)]));
+        assert_eq!(removed, vec![Row::new(vec![int(3), int(1)])]);
+        assert_eq!(index.get(&int(3)), vec![Row::new(vec![int(3), int(0)]), Row::new(vec![int(3), int(2)])]);
+
+        //a row with the key but other values removes nothing
+        assert!(index.remove(&Row::new(vec![int(3), int(9)])).is_empty());
+        assert_eq!(index.get(&int(3)).len(), 2);
+
+        index.remove(&Row::new(vec![int(3), int(0)]));
+        index.remove(&Row::new(vec![int(3), int(2)]));
+        assert!(index.get(&int(3)).is_empty());
+        assert_eq!(index.len(), 4);
+    }
+
+    #[test]
+    fn unique_remove_drops_whatever_holds_the_key() {
+        let mut index = Index::new(0, true, false);
+        index.insert(&Row::new(vec![int(1), int(0)]));
+
+        let removed = index.remove(&Row::new(vec![int(1), int(9)]));
+        assert_eq!(removed, vec![Row::new(vec![int(1), int(0)])]);
+        assert!(index.is_empty());
+    }
+}
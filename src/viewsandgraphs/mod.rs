@@ -1,3 +1,5 @@
 pub mod dfg;
+pub mod index;
 pub mod view;
-pub mod serverinfo;
\ No newline at end of file
+pub mod serverinfo;
+pub mod writededup;
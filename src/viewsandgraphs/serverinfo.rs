@@ -1,18 +1,30 @@
 use std::collections::HashMap;
+use serde_json::Value;
 use crate::types::permissiontype::PermissionType;
-use petgraph::graph::NodeIndex;
+use crate::units::grant::Grant;
+use crate::units::schema::Schema;
+use crate::operators::aggregation::Aggregation;
+use crate::operators::innerjoin::InnerJoin;
+use crate::operators::projection::Projection;
+use crate::operators::selection::Selection;
+use crate::viewsandgraphs::dfg::DataFlowGraph;
 
+//ServerInfo, what the server knows about each client path
+//path_permission_map: Read paths subscribe to their Leaf, Write paths send ServerChanges
+//path_grant_map: the Leaves and Roots a connection on the path may use
 pub struct ServerInfo {
     pub(crate) path_subgraph_map: HashMap<String, String>,
     pub(crate) path_permission_map: HashMap<String, PermissionType>,
+    pub(crate) path_grant_map: HashMap<String, Grant>,
 }
 
 impl ServerInfo {
     pub fn new() -> ServerInfo {
         let mut path_subgraph_map = HashMap::new(); 
         let mut path_permission_map = HashMap::new(); 
+        let path_grant_map = HashMap::new();
 
-        ServerInfo{ path_subgraph_map, path_permission_map }
+        ServerInfo{ path_subgraph_map, path_permission_map, path_grant_map }
     }
 
     pub fn add_path(&mut self, path: String, subgraph: String) {
@@ -22,4 +34,108 @@ impl ServerInfo {
     pub fn add_permission(&mut self, path: String, pt: PermissionType) {
         self.path_permission_map.insert(path, pt);
     }
-}
\ No newline at end of file
+
+    pub fn add_grant(&mut self, path: String, grant: Grant) {
+        self.path_grant_map.insert(path, grant);
+    }
+
+    /// Returns the grant for a path, a path without one can't read or write anything
+    pub fn grant(&self, path: &str) -> Grant {
+        self.path_grant_map.get(path).cloned().unwrap_or_default()
+    }
+
+    /// Fills in mat_view column_names and schema for client subgraphs that leave them out,
+    /// client Roots take their Schema from the server Leaf with the same root_pair_id
+    pub fn fill_schemas(&mut self, graph: &DataFlowGraph) -> Result<(), String> {
+        for (path, subgraph) in self.path_subgraph_map.iter_mut() {
+            if subgraph.is_empty() {
+                continue;
+            }
+
+            let mut json: Value = serde_json::from_str(subgraph).map_err(|err| format!("{}: {}", path, err))?;
+            fill_subgraph_schemas(&mut json, graph).map_err(|err| format!("{}: {}", path, err))?;
+            *subgraph = json.to_string();
+        }
+
+        Ok(())
+    }
+}
+
+//walks a client subgraph in operator order, subgraphs list parents before children
+fn fill_subgraph_schemas(json: &mut Value, graph: &DataFlowGraph) -> Result<(), String> {
+    let edges: Vec<(usize, usize)> = match json["edges"].as_array() {
+        Some(edges) => edges.iter()
+            .filter_map(|edge| Some((edge["parentindex"].as_u64()? as usize, edge["childindex"].as_u64()? as usize)))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let operators = match json["operators"].as_array_mut() {
+        Some(operators) => operators,
+        None => return Err("subgraph has no operators".to_owned()),
+    };
+
+    let mut schemas: Vec<Option<Schema>> = Vec::new();
+
+    for (index, op) in operators.iter_mut().enumerate() {
+        let parents: Vec<(usize, &Schema)> = edges.iter()
+            .filter(|(_, child)| *child == index)
+            .filter_map(|(parent, _)| Some((*parent, schemas.get(*parent)?.as_ref()?)))
+            .collect();
+        let parent_count = edges.iter().filter(|(_, child)| *child == index).count();
+
+        //a parent we know nothing about, nothing below it can be filled
+        if parents.len() != parent_count {
+            schemas.push(None);
+            continue;
+        }
+
+        let config = op["c"].clone();
+        let parse_err = |err: serde_json::Error| format!("operator {}: {}", index, err);
+        let only_parent = || match parents.as_slice() {
+            [(_, parent)] => Ok(*parent),
+            _ => Err(format!("operator {} expects exactly one parent", index)),
+        };
+
+        let schema = match op["t"].as_str() {
+            Some("Rootor") => match config["root_id"].as_str() {
+                Some(root_id) => graph.leaf_schema(root_id).cloned(),
+                None => None,
+            },
+            Some("InnerJoinor") => {
+                let join: InnerJoin = serde_json::from_value(config).map_err(parse_err)?;
+                Some(join.output_schema(&parents)?)
+            },
+            Some("Projector") => {
+                let projection: Projection = serde_json::from_value(config).map_err(parse_err)?;
+                Some(projection.output_schema(only_parent()?)?)
+            },
+            Some("Selector") => {
+                let selection: Selection = serde_json::from_value(config).map_err(parse_err)?;
+                Some(selection.output_schema(only_parent()?)?)
+            },
+            Some("Aggregator") => {
+                let aggregation: Aggregation = serde_json::from_value(config).map_err(parse_err)?;
+                Some(aggregation.output_schema(only_parent()?)?)
+            },
+            Some("Leafor") => {
+                let schema = parents.first().map(|(_, schema)| (*schema).clone());
+                let mat_view = &mut op["c"]["mat_view"];
+
+                if let (Some(schema), Some(view)) = (&schema, mat_view.as_object_mut()) {
+                    if !view.contains_key("column_names") && !view.contains_key("schema") {
+                        view.insert("column_names".to_owned(), serde_json::to_value(&schema.column_names).unwrap());
+                        view.insert("schema".to_owned(), serde_json::to_value(&schema.types).unwrap());
+                    }
+                }
+
+                schema
+            },
+            _ => None,
+        };
+
+        schemas.push(schema);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,240 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::units::change::Change;
+
+//how long a write id is remembered by default
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(60);
+
+//WriteOutcome, the result of one deduplicated write, None while it is still being applied
+#[derive(Debug, Default)]
+pub struct WriteOutcome {
+    result: Mutex<Option<Result<(), String>>>,
+    done: Condvar,
+}
+
+//WriteOutcome functions
+impl WriteOutcome {
+    /// Records the result and wakes every retry waiting on it
+    pub fn finish(&self, result: Result<(), String>) {
+        *self.result.lock().unwrap() = Some(result);
+        self.done.notify_all();
+    }
+
+    /// Returns the result, waiting for the write to be applied if it still is
+    pub fn wait(&self) -> Result<(), String> {
+        let mut result = self.result.lock().unwrap();
+
+        while result.is_none() {
+            result = self.done.wait(result).unwrap();
+        }
+
+        result.clone().unwrap()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.result.lock().unwrap().is_some()
+    }
+}
+
+//Applying, held by the write that claimed its ids until it finishes the outcome,
+//dropped unfinished, by a panic while applying, it fails the outcome so retries don't wait forever
+#[derive(Debug)]
+pub struct Applying(Arc<WriteOutcome>);
+
+//Applying functions
+impl Applying {
+    /// Records the write's result, see WriteOutcome::finish
+    pub fn finish(self, result: Result<(), String>) {
+        self.0.finish(result);
+    }
+}
+
+impl Drop for Applying {
+    fn drop(&mut self) {
+        if !self.0.is_finished() {
+            self.0.finish(Err("write failed while being applied".to_owned()));
+        }
+    }
+}
+
+//WriteKey, what names a write, write ids are only unique per client and a client_id per principal,
+//principal is None when authentication is off
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct WriteKey {
+    pub principal: Option<String>,
+    pub root_id: String,
+    pub client_id: String,
+    pub write_id: u64,
+}
+
+//Claim, what a write finds when it registers its ids
+//Apply: first time the ids are seen, the caller applies the write and finishes the outcome
+//Seen: the same write came first, the caller waits on its outcome instead
+#[derive(Debug)]
+pub enum Claim {
+    Apply(Applying),
+    Seen(Arc<WriteOutcome>),
+}
+
+//WriteDedup, outcomes of recent writes by WriteKey, with the changes each one carried
+//a retried write inside window gets the first outcome back instead of being applied again,
+//a different write reusing the ids is refused
+//writes are applied outside the lock, only a retry of a write still in flight waits for it
+//order: keys oldest first, so expired ones come off the front
+#[derive(Debug)]
+pub struct WriteDedup {
+    window: Duration,
+    seen: HashMap<WriteKey, (Vec<Change>, Arc<WriteOutcome>)>,
+    order: VecDeque<(Instant, WriteKey)>,
+}
+
+//WriteDedup functions
+impl WriteDedup {
+    //constructor
+    pub fn new(window: Duration) -> WriteDedup {
+        WriteDedup { window, seen: HashMap::new(), order: VecDeque::new() }
+    }
+
+    /// Changes how long write ids are remembered, applies to ones already seen too
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// Registers a write's ids and changes, see Claim, errors if the ids were used for other changes
+    pub fn claim(&mut self, key: WriteKey, changes: &[Change]) -> Result<Claim, String> {
+        self.expire(Instant::now());
+
+        if let Some((seen_changes, outcome)) = self.seen.get(&key) {
+            if seen_changes.as_slice() != changes {
+                return Err(format!("write_id {} of client {} was already used for a different write", key.write_id, key.client_id));
+            }
+
+            return Ok(Claim::Seen(Arc::clone(outcome)));
+        }
+
+        let outcome = Arc::new(WriteOutcome::default());
+        self.seen.insert(key.clone(), (changes.to_vec(), Arc::clone(&outcome)));
+        self.order.push_back((Instant::now(), key));
+
+        Ok(Claim::Apply(Applying(outcome)))
+    }
+
+    //writes still in flight are kept past the window, so a retry can't be applied alongside them
+    fn expire(&mut self, now: Instant) {
+        while let Some((at, key)) = self.order.front() {
+            if now.duration_since(*at) < self.window || !self.seen[key].1.is_finished() {
+                break;
+            }
+
+            let (_, key) = self.order.pop_front().unwrap();
+            self.seen.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use crate::types::changetype::ChangeType;
+    use crate::types::datatype::DataType;
+    use crate::units::row::Row;
+
+    fn key(principal: Option<&str>, write_id: u64) -> WriteKey {
+        WriteKey { principal: principal.map(str::to_owned), root_id: "Stories".to_owned(), client_id: "tab".to_owned(), write_id }
+    }
+
+    fn changes(value: i32) -> Vec<Change> {
+        vec![Change::new(ChangeType::Insertion, vec![Row::new(vec![DataType::Int(value)])])]
+    }
+
+    fn apply(claim: Claim) -> Applying {
+        match claim {
+            Claim::Apply(applying) => applying,
+            Claim::Seen(_) => panic!("write was already seen"),
+        }
+    }
+
+    fn seen(claim: Claim) -> Arc<WriteOutcome> {
+        match claim {
+            Claim::Seen(outcome) => outcome,
+            Claim::Apply(_) => panic!("write was applied again"),
+        }
+    }
+
+    #[test]
+    fn retry_gets_the_first_outcome() {
+        let mut dedup = WriteDedup::new(DEFAULT_DEDUP_WINDOW);
+        apply(dedup.claim(key(None, 1), &changes(1)).unwrap()).finish(Err("bad row".to_owned()));
+
+        let outcome = seen(dedup.claim(key(None, 1), &changes(1)).unwrap());
+        assert_eq!(outcome.wait(), Err("bad row".to_owned()));
+
+        //the next write_id is a new write
+        apply(dedup.claim(key(None, 2), &changes(1)).unwrap());
+    }
+
+    #[test]
+    fn ids_are_scoped_to_principal_and_root() {
+        let mut dedup = WriteDedup::new(DEFAULT_DEDUP_WINDOW);
+        apply(dedup.claim(key(Some("alice"), 1), &changes(1)).unwrap()).finish(Ok(()));
+
+        apply(dedup.claim(key(Some("bob"), 1), &changes(1)).unwrap()).finish(Ok(()));
+        apply(dedup.claim(WriteKey { root_id: "Votes".to_owned(), ..key(Some("alice"), 1) }, &changes(1)).unwrap()).finish(Ok(()));
+    }
+
+    #[test]
+    fn reused_id_with_other_changes_is_refused() {
+        let mut dedup = WriteDedup::new(DEFAULT_DEDUP_WINDOW);
+        apply(dedup.claim(key(None, 1), &changes(1)).unwrap()).finish(Ok(()));
+
+        assert!(dedup.claim(key(None, 1), &changes(2)).is_err());
+    }
+
+    #[test]
+    fn retry_waits_for_the_write_in_flight() {
+        let mut dedup = WriteDedup::new(DEFAULT_DEDUP_WINDOW);
+        let applying = apply(dedup.claim(key(None, 1), &changes(1)).unwrap());
+        let outcome = seen(dedup.claim(key(None, 1), &changes(1)).unwrap());
+
+        let retry = thread::spawn(move || outcome.wait());
+        thread::sleep(Duration::from_millis(20));
+        assert!(!retry.is_finished());
+
+        applying.finish(Ok(()));
+        assert_eq!(retry.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn write_that_panics_fails_its_retries() {
+        let mut dedup = WriteDedup::new(DEFAULT_DEDUP_WINDOW);
+        let applying = apply(dedup.claim(key(None, 1), &changes(1)).unwrap());
+        let outcome = seen(dedup.claim(key(None, 1), &changes(1)).unwrap());
+
+        assert!(thread::spawn(move || {
+            let _applying = applying;
+            panic!("change_to_root panicked");
+        }).join().is_err());
+
+        assert!(outcome.wait().is_err());
+    }
+
+    #[test]
+    fn ids_are_forgotten_after_the_window() {
+        let mut dedup = WriteDedup::new(Duration::from_millis(10));
+        apply(dedup.claim(key(None, 1), &changes(1)).unwrap()).finish(Ok(()));
+        let in_flight = apply(dedup.claim(key(None, 2), &changes(1)).unwrap());
+
+        thread::sleep(Duration::from_millis(20));
+
+        //write 2 is still being applied, so it and everything after it are kept
+        apply(dedup.claim(key(None, 1), &changes(1)).unwrap()).finish(Ok(()));
+        seen(dedup.claim(key(None, 2), &changes(1)).unwrap());
+
+        in_flight.finish(Ok(()));
+        dedup.expire(Instant::now() + Duration::from_millis(20));
+        assert!(dedup.seen.is_empty());
+    }
+}